@@ -1,16 +1,30 @@
-use std::io;
 use std::fs;
+use std::io;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+include!("src/common.rs");
 
-const DEFAULT_VERUS_REPO: &str = "https://github.com/verus-lang/verus.git";
 const DEFAULT_VERUS_COMMIT: &str = "HEAD";
 
+/// Arguments always passed to Verus's internal vargo to build Verus
+const VERUS_BASE_BUILD_ARGS: [&str; 2] = ["build", "--release"];
+
+/// Solvers other than Z3 that Verus can be built with; each is included if a
+/// build feature names it or `VERUS_<SOLVER>_PATH` is set
+const EXTRA_SOLVERS: [&str; 1] = ["cvc5"];
+
 /// Add all subdirectories and files of `dir` to the watch list
 /// if they don't start with "target"
-fn add_non_target_watch_list(dir: &PathBuf) -> Result<(), Box<dyn Error>> {
+fn add_non_target_watch_list(dir: &Path) -> Result<(), Box<dyn Error>> {
     for entry in dir.read_dir()? {
         let entry = entry?;
         let file_name = entry.file_name().to_str().ok_or("File name contains invalid character")?.to_string();
@@ -23,19 +37,135 @@ fn add_non_target_watch_list(dir: &PathBuf) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-/// Build Verus at a checked-out repo
-fn build_verus(path: &Path) -> Result<(), Box<dyn Error>> {
-    // Download Z3 using a script in Verus (source/tools/get-z3.sh)
-    // TODO: write this directly in Rust
-    if !path.join("source").join("z3").exists() {
+/// Whether cargo is in offline mode (`CARGO_NET_OFFLINE`)
+fn is_offline() -> bool {
+    matches!(env::var("CARGO_NET_OFFLINE").as_deref(), Ok("true") | Ok("1"))
+}
+
+/// Read the Z3 version expected by Verus from `source/tools/get-z3.sh`
+/// (a line of the form `z3_version="4.12.5"`)
+fn expected_z3_version(verus_repo: &Path) -> Option<String> {
+    let script = fs::read_to_string(verus_repo.join("source").join("tools").join("get-z3.sh")).ok()?;
+    let line = script.lines().find_map(|line| line.trim().strip_prefix("z3_version="))?;
+    Some(line.trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// Check the Z3 executable against the checksum in `VERUS_Z3_SHA256` (if set)
+/// and against the version in `VERUS_Z3_VERSION` (or the one expected by Verus)
+fn check_z3(verus_repo: &Path, z3: &Path) -> Result<(), Box<dyn Error>> {
+    if let Ok(expected) = env::var("VERUS_Z3_SHA256") {
+        let actual = format!("{:x}", Sha256::digest(fs::read(z3)?));
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            Err(format!("Z3 at {} has SHA-256 {}, but VERUS_Z3_SHA256 is {}", z3.display(), actual, expected))?;
+        }
+    }
+
+    let expected = env::var("VERUS_Z3_VERSION").ok().or_else(|| expected_z3_version(verus_repo));
+    let Some(expected) = expected else {
+        println!("cargo:warning=Unable to determine the Z3 version expected by Verus, skipping the version check");
+        return Ok(());
+    };
+
+    match z3_version(z3) {
+        Some(actual) if actual == expected => Ok(()),
+        Some(actual) => Err(format!("Z3 at {} has version {}, but Verus expects {}", z3.display(), actual, expected))?,
+        None => Err(format!("Failed to get the version of Z3 at {}", z3.display()))?,
+    }
+}
+
+/// Cargo features of Verus to build with (`VERUS_BUILD_FEATURES`, separated by commas or spaces)
+fn build_features() -> Vec<String> {
+    env::var("VERUS_BUILD_FEATURES").unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Arguments passed to Verus's internal vargo to build Verus,
+/// including `VERUS_BUILD_FEATURES` and `VERUS_BUILD_ARGS`
+fn verus_build_args() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut args = VERUS_BASE_BUILD_ARGS.map(str::to_string).to_vec();
+
+    let features = build_features();
+    if !features.is_empty() {
+        args.push("--features".to_string());
+        args.push(features.join(","));
+    }
+
+    if let Ok(extra_args) = env::var("VERUS_BUILD_ARGS") {
+        args.extend(shell_words::split(&extra_args)
+            .map_err(|err| format!("Failed to parse VERUS_BUILD_ARGS: {}", err))?);
+    }
+
+    Ok(args)
+}
+
+/// Environment variable pointing to an existing executable of a solver (e.g. `VERUS_Z3_PATH`)
+fn solver_path_var(solver: &str) -> String {
+    format!("VERUS_{}_PATH", solver.to_uppercase())
+}
+
+/// Solvers to include in the build: Z3 and the extra solvers requested
+fn required_solvers() -> Vec<&'static str> {
+    let features = build_features();
+    let mut solvers = vec!["z3"];
+    solvers.extend(EXTRA_SOLVERS.into_iter().filter(|solver| {
+        features.iter().any(|feature| feature.contains(solver)) || env::var(solver_path_var(solver)).is_ok()
+    }));
+    solvers
+}
+
+/// Locate the executable of a solver: the one given in `VERUS_<SOLVER>_PATH` (left where it is),
+/// or `source/<solver>` in the Verus repo, downloaded with Verus's `tools/get-<solver>.sh` if missing
+fn fetch_solver(path: &Path, solver: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let solver_path = path.join("source").join(solver);
+    let path_var = solver_path_var(solver);
+
+    if let Ok(given) = env::var(&path_var) {
+        // Use the given solver instead of downloading one
+        if !Path::new(&given).is_file() {
+            Err(format!("{} is set to {}, which is not a file", path_var, given))?;
+        }
+        return Ok(PathBuf::from(given));
+    }
+
+    if !solver_path.exists() {
+        if is_offline() {
+            Err(format!(
+                "{} needs to be downloaded, but cargo is in offline mode (CARGO_NET_OFFLINE); \
+                set {} to an executable instead",
+                solver, path_var,
+            ))?;
+        }
+
+        // Download the solver using a script in Verus (e.g. source/tools/get-z3.sh)
+        // TODO: write this directly in Rust
+        let script = format!("tools/get-{}.sh", solver);
+        if !path.join("source").join(&script).exists() {
+            Err(format!("This version of Verus has no {} to download {}; set {} instead", script, solver, path_var))?;
+        }
         if !Command::new("sh")
             .current_dir(path.join("source"))
-            .args(["tools/get-z3.sh"])
+            .arg(&script)
             .status()?.success() {
-            Err("Failed to download Z3")?;
+            Err(format!("Failed to download {}", solver))?;
         }
     }
 
+    Ok(solver_path)
+}
+
+/// Build Verus at a checked-out repo with the given arguments to its internal vargo
+fn build_verus(path: &Path, build_args: &[String]) -> Result<(), Box<dyn Error>> {
+    let solvers = required_solvers().into_iter()
+        .map(|solver| Ok((solver, fetch_solver(path, solver)?)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    if let Some((_, z3)) = solvers.iter().find(|(solver, _)| *solver == "z3") {
+        check_z3(path, z3)?;
+    }
+
     // First call cargo to build verus's internal vargo
     if !Command::new("cargo")
         .current_dir(path.join("tools").join("vargo"))
@@ -44,36 +174,69 @@ fn build_verus(path: &Path) -> Result<(), Box<dyn Error>> {
         Err("Failed to build Verus's internal vargo")?;
     }
 
-    // Call internal vargo to build Verus
+    // Call internal vargo to build Verus (passing on the given solvers,
+    // so that they are not expected in the repo)
     if !Command::new(path
             .join("tools").join("vargo")
             .join("target").join("release").join("vargo"))
         .current_dir(path.join("source"))
         .env_clear()
         .env("PATH", env::var("PATH")?)
-        .args(["build", "--release"])
+        .envs(env::var("CARGO_NET_OFFLINE").map(|offline| ("CARGO_NET_OFFLINE", offline)))
+        .envs(solvers.iter().filter_map(|(solver, _)| {
+            let path_var = solver_path_var(solver);
+            env::var(&path_var).ok().map(|given| (path_var, given))
+        }))
+        .args(build_args)
         .status()?.success() {
         Err("Failed to build Verus")?;
     }
 
+    // Make sure that the solvers are next to the Verus executables
+    // (a given solver replaces whatever the build put there)
+    let release_dir = path.join("source").join("target-verus").join("release");
+    for (solver, solver_path) in solvers {
+        if env::var(solver_path_var(solver)).is_ok() || !release_dir.join(solver).exists() {
+            fs::copy(&solver_path, release_dir.join(solver))
+                .map_err(|err| format!("Failed to copy {} from {}: {}", solver, solver_path.display(), err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pack directories into a gzip-compressed tarball (each under the given
+/// path in the archive), recording which files are executable and keeping symlinks as symlinks
+fn pack_dirs(dirs: &[(&str, &Path)], archive: &Path) -> Result<(), Box<dyn Error>> {
+    let encoder = GzEncoder::new(fs::File::create(archive)?, Compression::best());
+    let mut builder = tar::Builder::new(encoder);
+    // Only keep whether files are executable, so that the archive (and its hash)
+    // does not change when the same files are packed again
+    builder.mode(tar::HeaderMode::Deterministic);
+    builder.follow_symlinks(false);
+    for (path, src) in dirs {
+        builder.append_dir_all(path, src)?;
+    }
+    builder.into_inner()?.finish()?;
     Ok(())
 }
 
-// Recursively copy a directory
-fn copy_dir(src: &Path, dst: &Path) -> io::Result<()> {
-    fs::create_dir_all(dst)?; // Create destination directory
+/// Recursively copy a source directory, skipping `target*` and `.git` directories
+fn copy_source_dir(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dst)?;
 
-    // Iterate over entries
     for entry in fs::read_dir(src)? {
         let entry = entry?;
         let file_type = entry.file_type()?;
+        let file_name = entry.file_name().to_str().ok_or("File name contains invalid character")?.to_string();
         let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+        let dst_path = dst.join(&file_name);
 
         if file_type.is_dir() {
-            // Recursively copy subdirectories
-            copy_dir(&src_path, &dst_path)?;
-        } else {
+            if !file_name.starts_with("target") && file_name != ".git" {
+                copy_source_dir(&src_path, &dst_path)?;
+            }
+        } else if file_type.is_file() {
             fs::copy(&src_path, &dst_path)?;
         }
     }
@@ -81,33 +244,551 @@ fn copy_dir(src: &Path, dst: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Copy the source of the crate at `crate_dir` (in `verus_repo`) to the same relative path
+/// under `dest`, along with all its path dependencies, so that the copies still build
+fn copy_crate_source(verus_repo: &Path, crate_dir: &Path, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let crate_dir = crate_dir.canonicalize()?;
+    let relative_dir = crate_dir.strip_prefix(verus_repo)
+        .map_err(|_| format!("Path dependency {} is outside of the Verus repo", crate_dir.display()))?;
+
+    // Already copied (e.g. shared by multiple crates)
+    if dest.join(relative_dir).exists() {
+        return Ok(());
+    }
+    copy_source_dir(&crate_dir, &dest.join(relative_dir))?;
+
+    let manifest = fs::read_to_string(crate_dir.join("Cargo.toml"))?.parse::<toml::Table>()?;
+
+    // Dependency tables that are needed when the crate is used as a dependency
+    let mut dep_tables = vec![manifest.get("dependencies"), manifest.get("build-dependencies")];
+    if let Some(targets) = manifest.get("target").and_then(|targets| targets.as_table()) {
+        for target in targets.values() {
+            dep_tables.push(target.get("dependencies"));
+            dep_tables.push(target.get("build-dependencies"));
+        }
+    }
+
+    for table in dep_tables.into_iter().flatten().filter_map(|table| table.as_table()) {
+        for dep in table.values() {
+            if let Some(path) = dep.get("path").and_then(|path| path.as_str()) {
+                copy_crate_source(verus_repo, &crate_dir.join(path), dest)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Directory of the Verus build cache: `VERUS_BUILD_CACHE` if set (`off` disables the cache),
+/// otherwise `$CARGO_HOME/vargo-build-cache` (with `~/.cargo` as the default `CARGO_HOME`)
+fn build_cache_dir() -> Option<PathBuf> {
+    match env::var("VERUS_BUILD_CACHE") {
+        Ok(dir) if dir == "off" => None,
+        Ok(dir) => Some(PathBuf::from(dir)),
+        Err(_) => env::var("CARGO_HOME").map(PathBuf::from)
+            .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".cargo")))
+            .ok()
+            .map(|cargo_home| cargo_home.join("vargo-build-cache")),
+    }
+}
+
+/// Key of a Verus build in the cache: a hash of the repo, the commit,
+/// the build arguments and the provided solvers (if any)
+fn build_cache_key(repo: &str, commit: &str, build_args: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    for part in [repo, commit, &build_args.join(" ")] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    for solver in required_solvers() {
+        hasher.update(solver);
+        if let Ok(given) = env::var(solver_path_var(solver)) {
+            hasher.update(fs::read(&given).map_err(|err| format!("Failed to read {} from {}: {}", solver, given, err))?);
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Store a copy of the built targets (`release`) at `cached`
+fn store_in_build_cache(release: &Path, cached: &Path) -> Result<(), Box<dyn Error>> {
+    let parent = cached.parent().ok_or("Cache entry has no parent directory")?;
+    fs::create_dir_all(parent)?;
+
+    // Copy to a staging directory first so that `cached` is always either absent or complete
+    let staging = parent.join(format!(".{}.partial-{}",
+        cached.file_name().and_then(|name| name.to_str()).ok_or("Path contains invalid character")?,
+        std::process::id(),
+    ));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    copy_dir(release, &staging)?;
+
+    // Another build might have stored the same entry in the meantime
+    if fs::rename(&staging, cached).is_err() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    Ok(())
+}
+
+/// Resolve a ref (`HEAD`, a branch, a tag or a full commit hash) of a remote repo
+/// to a commit without cloning it, along with the branch name if the ref is a branch
+fn resolve_remote_ref(repo: &str, reference: &str) -> Option<(String, Option<String>)> {
+    if reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some((reference.to_string(), None));
+    }
+
+    let output = Command::new("git").args(["ls-remote", "--symref", repo, reference]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let mut commit = None;
+    let mut branch = None;
+    for line in String::from_utf8(output.stdout).ok()?.lines() {
+        let (target, name) = line.split_once('\t')?;
+        if let Some(symref) = target.strip_prefix("ref: ") {
+            // e.g. `ref: refs/heads/main  HEAD`
+            branch = symref.strip_prefix("refs/heads/").map(str::to_string);
+        } else if name.ends_with("^{}") {
+            // Peeled annotated tag
+            commit = Some(target.to_string());
+        } else if commit.is_none() || branch.is_none() {
+            if let Some(name) = name.strip_prefix("refs/heads/") {
+                branch = Some(name.to_string());
+            }
+            commit.get_or_insert_with(|| target.to_string());
+        }
+    }
+
+    Some((commit?, branch))
+}
+
+/// Fetch only `commit` of a remote repo into an empty directory `dest`
+/// and check it out (on `branch` if given, otherwise detached)
+fn shallow_fetch(repo: &str, commit: &str, branch: Option<&str>, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let git = |args: &[&str]| -> Result<(), Box<dyn Error>> {
+        if !Command::new("git").current_dir(dest).args(args).status()?.success() {
+            Err(format!("Failed to run git {}", args.join(" ")))?;
+        }
+        Ok(())
+    };
+
+    git(&["init", "--quiet"])?;
+    git(&["remote", "add", "origin", repo])?;
+    git(&["fetch", "--depth", "1", "origin", commit])?;
+    match branch {
+        Some(branch) => git(&["checkout", "-B", branch, "FETCH_HEAD"]),
+        None => git(&["checkout", "--detach", "FETCH_HEAD"]),
+    }
+}
+
+/// Unpack a (optionally gzip-compressed) Verus source tarball to `dest`, returning the root of
+/// the source tree and the commit recorded by `git archive` (e.g. in GitHub archives), if any
+fn unpack_source_tarball(tarball: &Path, dest: &Path) -> Result<(PathBuf, Option<String>), Box<dyn Error>> {
+    let commit = unpack_tarball(tarball, dest)?;
+    let root = archive_root(dest)?;
+
+    if !root.join("source").is_dir() {
+        Err(format!("{} does not look like a Verus source tarball (no `source` directory)", tarball.display()))?;
+    }
+
+    Ok((root, commit))
+}
+
+/// Unpack a `.tar`, `.tar.gz` or `.tgz` archive to `dest`, returning the commit
+/// recorded by `git archive` (e.g. in GitHub archives), if any
+fn unpack_tarball(tarball: &Path, dest: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let file = fs::File::open(tarball)
+        .map_err(|err| format!("Failed to open {}: {}", tarball.display(), err))?;
+    let reader: Box<dyn io::Read> = if tarball.extension().is_some_and(|ext| ext == "tar") {
+        Box::new(file)
+    } else {
+        Box::new(GzDecoder::new(file))
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    let mut commit = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        // `git archive` stores the commit in the `comment` of a global pax header
+        if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+            if let Some(extensions) = entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    if extension.key() == Ok("comment") {
+                        commit = Some(extension.value()?.trim().to_string());
+                    }
+                }
+            }
+            continue;
+        }
+
+        entry.unpack_in(dest)?;
+    }
+
+    Ok(commit)
+}
+
+/// Root of an unpacked archive at `dir`: archives usually have a single top-level directory
+fn archive_root(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    Ok(match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => entry.path(),
+        _ => dir.to_path_buf(),
+    })
+}
+
+/// Sample program that the embedded Verus must verify (see `self_test`)
+const SELF_TEST_SAMPLE: &str = "\
+use vstd::prelude::*;
+
+verus! {
+
+pub open spec fn spec_fib(n: int) -> int
+    decreases n
+{
+    if n <= 0 {
+        0
+    } else if n == 1 {
+        1
+    } else {
+        spec_fib(n - 1) + spec_fib(n - 2)
+    }
+}
+
+pub proof fn spec_fib_non_negative(n: int)
+    ensures spec_fib(n) >= 0
+    decreases n
+{
+    if n > 1 {
+        spec_fib_non_negative(n - 1);
+        spec_fib_non_negative(n - 2);
+    }
+}
+
+pub fn fib_2() -> (res: u64)
+    ensures res == spec_fib(2)
+{
+    1
+}
+
+}
+";
+
+/// Sample program that the embedded Verus must reject (see `self_test`)
+const SELF_TEST_FAILING_SAMPLE: &str = "\
+use vstd::prelude::*;
+
+verus! {
+
+pub proof fn wrong() {
+    assert(false);
+}
+
+}
+";
+
+/// Run `verus` on a sample file in `dir`, returning whether it succeeded and its output
+fn run_self_test_sample(verus: &Path, dir: &Path, name: &str, sample: &str) -> Result<(bool, String), Box<dyn Error>> {
+    let sample_path = dir.join(format!("{}.rs", name));
+    fs::write(&sample_path, sample)?;
+
+    let output = Command::new(verus)
+        .current_dir(dir)
+        .arg(&sample_path)
+        .args(["--crate-type=lib", "--crate-name", name])
+        .args(["--emit=dep-info,metadata", "--out-dir"]).arg(dir)
+        .arg("--no-report-long-running")
+        .arg("--compile")
+        .arg("--export").arg(dir.join(format!("{}.verusdata", name)))
+        .output()
+        .map_err(|err| format!("Failed to run {}: {}", verus.display(), err))?;
+
+    Ok((
+        output.status.success(),
+        format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+    ))
+}
+
+/// Check that the Verus release at `release_dir` works before packing it:
+/// Z3 runs, a sample program verifies (and exports), and a wrong program fails to verify
+fn self_test(release_dir: &Path, work_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let z3 = release_dir.join("z3");
+    if z3_version(&z3).is_none() {
+        Err("Self-test of the embedded Verus failed: `z3 --version` did not report a version")?;
+    }
+
+    fs::create_dir_all(work_dir)?;
+    let verus = release_dir.join("verus");
+
+    let (success, output) = run_self_test_sample(&verus, work_dir, "vargo_self_test", SELF_TEST_SAMPLE)?;
+    if !success {
+        eprintln!("{}", output);
+        Err("Self-test of the embedded Verus failed: verification of a sample program failed (see the output above)")?;
+    }
+    if !work_dir.join("vargo_self_test.verusdata").exists() {
+        eprintln!("{}", output);
+        Err("Self-test of the embedded Verus failed: `--export` did not produce any output (see the output above)")?;
+    }
+
+    let (success, _) = run_self_test_sample(&verus, work_dir, "vargo_self_test_failing", SELF_TEST_FAILING_SAMPLE)?;
+    if success {
+        Err("Self-test of the embedded Verus failed: verification of a wrong program succeeded")?;
+    }
+
+    Ok(())
+}
+
+/// Files that a (prebuilt) Verus release must contain
+const VERUS_RELEASE_FILES: [&str; 5] = ["verus", "rust_verify", "z3", "vstd.vir", "libvstd.rlib"];
+
+/// Copy a prebuilt Verus release (a directory, or a `.zip`, `.tar`, `.tar.gz` or `.tgz` archive
+/// such as the official release archives) to `dest`, and check that it is complete
+fn copy_prebuilt(prebuilt: &Path, dest: &Path) -> Result<(), Box<dyn Error>> {
+    if prebuilt.is_dir() {
+        copy_dir(prebuilt, dest)?;
+    } else {
+        let unpacked = dest.with_extension("unpacked");
+        fs::create_dir_all(&unpacked)?;
+
+        if prebuilt.extension().is_some_and(|ext| ext == "zip") {
+            if !Command::new("unzip")
+                .args(["-q", "-o"]).arg(prebuilt).arg("-d").arg(&unpacked)
+                .status()?.success() {
+                Err(format!("Failed to unzip {}", prebuilt.display()))?;
+            }
+        } else {
+            unpack_tarball(prebuilt, &unpacked)?;
+        }
+
+        fs::rename(archive_root(&unpacked)?, dest)?;
+    }
+
+    let missing = VERUS_RELEASE_FILES.into_iter()
+        .filter(|file| !dest.join(file).exists())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        Err(format!(
+            "{} does not look like a Verus release (missing {})",
+            prebuilt.display(), missing.join(", "),
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Commit of a prebuilt Verus release, from its `version.json` (if any)
+fn prebuilt_commit(release_dir: &Path) -> Option<String> {
+    let version = fs::read_to_string(release_dir.join("version.json")).ok()?;
+    let version = serde_json::from_str::<serde_json::Value>(&version).ok()?;
+    ["commit", "sha"].into_iter()
+        .find_map(|key| version["verus"][key].as_str())
+        .map(str::to_string)
+}
+
+/// Run a git command in `repo` and return its trimmed stdout (if successful)
+fn git_output(repo: &Path, args: &[&str]) -> Option<String> {
+    // Do not pick up an enclosing repo (e.g. for unpacked archives)
+    if !repo.join(".git").exists() {
+        return None;
+    }
+
+    let output = Command::new("git").current_dir(repo).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+/// Get the version number of a Z3 executable (from `Z3 version 4.12.5 - 64 bit`)
+fn z3_version(z3: &Path) -> Option<String> {
+    let output = Command::new(z3).arg("--version").output().ok()?;
+    let output = String::from_utf8(output.stdout).ok()?;
+    let mut words = output.split_whitespace();
+    words.find(|word| *word == "version")?;
+    Some(words.next()?.to_string())
+}
+
+/// Build time in the ISO 8601 format (UTC): `SOURCE_DATE_EPOCH` if set (for reproducible builds),
+/// otherwise the current time
+fn build_date() -> Result<String, Box<dyn Error>> {
+    let secs = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch.trim().parse::<u64>()
+            .map_err(|err| format!("Invalid SOURCE_DATE_EPOCH {:?}: {}", epoch, err))?,
+        Err(_) => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+
+    // Convert days since 1970-01-01 to a civil date (Howard Hinnant's `civil_from_days`)
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60,
+    ))
+}
+
+/// Emit the provenance of the Verus build as compile-time environment variables
+/// (read by `vargo version`); `source` is the kind of source Verus came from
+/// (`remote`, `local`, `tarball` or `prebuilt`), and `origin` and `commit` override what git reports for the repo
+fn emit_provenance(
+    verus_repo: &Path,
+    release_dir: &Path,
+    source: &str,
+    remote_repo: Option<&str>,
+    origin: Option<&str>,
+    commit: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let repo = remote_repo.or(origin).map(str::to_string)
+        .or_else(|| git_output(verus_repo, &["remote", "get-url", "origin"]))
+        .unwrap_or_else(|| verus_repo.display().to_string());
+    let branch = git_output(verus_repo, &["rev-parse", "--abbrev-ref", "HEAD"]).unwrap_or_default();
+    let commit = commit.map(str::to_string)
+        .or_else(|| git_output(verus_repo, &["rev-parse", "HEAD"]))
+        .unwrap_or_default();
+    let dirty = git_output(verus_repo, &["status", "--porcelain", "--untracked-files=no"])
+        .map(|status| !status.is_empty());
+    let z3_version = z3_version(&release_dir.join("z3"))
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=VARGO_VERUS_REPO={}", repo);
+    println!("cargo:rustc-env=VARGO_VERUS_BRANCH={}", branch);
+    println!("cargo:rustc-env=VARGO_VERUS_COMMIT={}", commit);
+    println!("cargo:rustc-env=VARGO_VERUS_DIRTY={}", match dirty {
+        Some(true) => "true",
+        Some(false) => "false",
+        None => "",
+    });
+    println!("cargo:rustc-env=VARGO_VERUS_SOURCE={}", source);
+    println!("cargo:rustc-env=VARGO_Z3_VERSION={}", z3_version);
+    println!("cargo:rustc-env=VARGO_VERUS_BUILD_FEATURES={}", build_features().join(","));
+    println!("cargo:rustc-env=VARGO_VERUS_BUILD_ARGS={}", env::var("VERUS_BUILD_ARGS").unwrap_or_default());
+    println!("cargo:rustc-env=VARGO_BUILD_DATE={}", build_date()?);
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Note on environment variables:
     // There are two options for specifying which Verus version to use
     // 1. Use VERUS_REPO and optional VERUS_BRANCH and/or optional VERUS_COMMIT
     // 2. Use VERUS_LOCAL which points to an existing local checked-out Verus repo
-    // If none of 1. and 2. are specified, we checkout DEFAULT_VERUS_REPO at DEFAULT_VERUS_COMMIT
+    // 3. Use VERUS_SOURCE_TARBALL which points to a (.tar, .tar.gz or .tgz) source archive of Verus
+    // 4. Use VERUS_PREBUILT which points to a prebuilt Verus release (a directory, or a .zip, .tar,
+    //    .tar.gz or .tgz archive), which is packed as is without building Verus
+    // If none of 1., 2., 3. and 4. are specified, we checkout DEFAULT_VERUS_REPO at DEFAULT_VERUS_COMMIT
+    //
+    // The built Verus is tested on sample programs before packing (VERUS_SELF_TEST=off disables this)
+    //
+    // Optionally, VERUS_Z3_PATH points to a Z3 executable to use instead of downloading one,
+    // which is checked against VERUS_Z3_VERSION (defaults to the version expected by Verus)
+    // and VERUS_Z3_SHA256 (if set)
+    //
+    // VERUS_BUILD_FEATURES and VERUS_BUILD_ARGS are passed to Verus's internal vargo;
+    // extra solvers (e.g. cvc5) are downloaded if a feature names them, or taken from VERUS_<SOLVER>_PATH
+
+    // Without the `embedded-verus` feature, there is nothing to build
+    if env::var("CARGO_FEATURE_EMBEDDED_VERUS").is_err() {
+        println!("cargo:rerun-if-changed=build.rs");
+        return Ok(());
+    }
 
     println!("cargo:rerun-if-env-changed=VERUS_REPO");
     println!("cargo:rerun-if-env-changed=VERUS_BRANCH");
     println!("cargo:rerun-if-env-changed=VERUS_COMMIT");
     println!("cargo:rerun-if-env-changed=VERUS_LOCAL");
+    println!("cargo:rerun-if-env-changed=VERUS_SOURCE_TARBALL");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-env-changed=VERUS_PREBUILT");
+    println!("cargo:rerun-if-env-changed=VERUS_Z3_PATH");
+    println!("cargo:rerun-if-env-changed=VERUS_Z3_VERSION");
+    println!("cargo:rerun-if-env-changed=VERUS_Z3_SHA256");
+    println!("cargo:rerun-if-env-changed=CARGO_NET_OFFLINE");
+    println!("cargo:rerun-if-env-changed=VERUS_BUILD_CACHE");
+    println!("cargo:rerun-if-env-changed=VERUS_SELF_TEST");
+    println!("cargo:rerun-if-env-changed=VERUS_BUILD_FEATURES");
+    println!("cargo:rerun-if-env-changed=VERUS_BUILD_ARGS");
+    for solver in EXTRA_SOLVERS {
+        println!("cargo:rerun-if-env-changed={}", solver_path_var(solver));
+    }
+
+    let sources = ["VERUS_LOCAL", "VERUS_REPO", "VERUS_SOURCE_TARBALL", "VERUS_PREBUILT"]
+        .into_iter().filter(|var| env::var(var).is_ok()).collect::<Vec<_>>();
+    if sources.len() > 1 {
+        Err(format!("Cannot specify more than one of {}", sources.join(", ")))?;
+    }
+
+    if env::var("VERUS_PREBUILT").is_ok() {
+        for var in ["VERUS_BUILD_FEATURES", "VERUS_BUILD_ARGS"] {
+            if env::var(var).is_ok() {
+                Err(format!("{} cannot be used with VERUS_PREBUILT, which is already built", var))?;
+            }
+        }
+        for solver in ["z3"].into_iter().chain(EXTRA_SOLVERS) {
+            if env::var(solver_path_var(solver)).is_ok() {
+                Err(format!("{} cannot be used with VERUS_PREBUILT, which already contains the solvers", solver_path_var(solver)))?;
+            }
+        }
+    }
 
-    if env::var("VERUS_LOCAL").is_ok() && env::var("VERUS_REPO").is_ok() {
-        Err("Cannot specify both VERUS_LOCAL and VERUS_REPO")?;
+    for solver in ["z3"].into_iter().chain(EXTRA_SOLVERS) {
+        if let Ok(given) = env::var(solver_path_var(solver)) {
+            println!("cargo:rerun-if-changed={}", given);
+        }
     }
 
+    let build_args = verus_build_args()?;
+
     let tmp_verus_dir = tempdir::TempDir::new("verus")?;
+    let mut remote_repo_url = None;
+    let mut origin = None;
+    let mut known_commit = None;
+    let prebuilt = env::var("VERUS_PREBUILT").ok();
+    let source;
 
-    let verus_repo = if let Ok(local_repo) = env::var("VERUS_LOCAL") {
+    let verus_repo = if let Some(prebuilt) = &prebuilt {
+        println!("cargo:rerun-if-changed={}", prebuilt);
+
+        origin = Some(prebuilt.clone());
+        source = "prebuilt";
+        tmp_verus_dir.path().to_owned()
+    } else if let Ok(tarball) = env::var("VERUS_SOURCE_TARBALL") {
+        println!("cargo:rerun-if-changed={}", tarball);
+
+        let (root, commit) = unpack_source_tarball(Path::new(&tarball), tmp_verus_dir.path())?;
+        origin = Some(tarball);
+        known_commit = commit;
+        source = "tarball";
+        root
+    } else if let Ok(local_repo) = env::var("VERUS_LOCAL") {
         let local_repo = PathBuf::from(local_repo);
 
+        // Also rebuild if the checked-out commit changes (for the provenance record)
+        let git_head = local_repo.join(".git").join("HEAD");
+        if git_head.exists() {
+            println!("cargo:rerun-if-changed={}", git_head.to_str().ok_or("Path contains invalid character")?);
+        }
+
         // For local Verus repo, we monitor its source changes
         // Add everything in source to the watch list except for target*
         // TOOD: might be incorrect for future versions of Verus
         add_non_target_watch_list(&local_repo.join("source"))?;
         add_non_target_watch_list(&local_repo.join("tools").join("vargo"))?;
 
+        source = "local";
         local_repo
     } else {
         // Otherwise check out a remote repo
@@ -120,54 +801,162 @@ fn main() -> Result<(), Box<dyn Error>> {
             (DEFAULT_VERUS_REPO.to_string(), true)
         };
 
-        // Clone the remote repo at `tmp_verus_dir`
-        if !Command::new("git")
-            .args(["clone", &remote_repo, tmp_verus_dir_str])
-            .status()?.success() {
-            Err("Failed to clone Verus repo")?;
+        if is_offline() {
+            Err(format!(
+                "Cannot clone {} in offline mode (CARGO_NET_OFFLINE); set VERUS_LOCAL to a checked-out \
+                Verus repo or VERUS_SOURCE_TARBALL to a Verus source archive (and VERUS_Z3_PATH to a Z3 executable)",
+                remote_repo,
+            ))?;
         }
 
-        // Checkout the target branch if specified
-        if let Ok(branch) = env::var("VERUS_BRANCH") {
-            if !Command::new("git")
-                .current_dir(&tmp_verus_dir)
-                .args(["fetch", "origin", &branch])
-                .status()?.success() {
-                Err("Failed to set Verus branch")?;
+        // Resolve the target commit first, so that only that commit needs to be fetched
+        let branch = env::var("VERUS_BRANCH").ok();
+        let commit = env::var("VERUS_COMMIT").ok()
+            // If a VERUS_REPO is specified, then `DEFAULT_VERUS_COMMIT` is ignored
+            .or(is_default.then(|| DEFAULT_VERUS_COMMIT.to_string()));
+        let reference = match (&commit, &branch) {
+            // Checking out HEAD after the branch stays on the branch
+            (Some(commit), Some(branch)) if commit == "HEAD" => format!("refs/heads/{}", branch),
+            (Some(commit), _) => commit.clone(),
+            (None, Some(branch)) => format!("refs/heads/{}", branch),
+            (None, None) => "HEAD".to_string(),
+        };
+
+        let fetched = match resolve_remote_ref(&remote_repo, &reference) {
+            Some((commit, branch)) => {
+                let result = shallow_fetch(&remote_repo, &commit, branch.as_deref(), tmp_verus_dir.path());
+                if let Err(err) = &result {
+                    // Fall back to a full clone (e.g. if the server does not allow fetching by commit)
+                    println!("cargo:warning=Shallow fetch of {} failed ({}), cloning instead", commit, err);
+                    fs::remove_dir_all(tmp_verus_dir.path().join(".git"))?;
+                }
+                result.is_ok()
             }
+            None => false,
+        };
 
+        if !fetched {
+            // Clone the remote repo at `tmp_verus_dir`
             if !Command::new("git")
-                .current_dir(&tmp_verus_dir)
-                .args(["checkout", &branch])
+                .args(["clone", &remote_repo, tmp_verus_dir_str])
                 .status()?.success() {
-                Err("Failed to set Verus branch")?;
+                Err("Failed to clone Verus repo")?;
             }
-        }
 
-        // Checkout the target commit if specified
-        if let Ok(commit) = env::var("VERUS_COMMIT").or(
-            if is_default { Ok(DEFAULT_VERUS_COMMIT.to_string()) }
-            else { Err("No commit specified") } // If a VERUS_REPO is specified, then `DEFAUL_VERUS_COMMIT` is ignored
-        ) {
-            if !Command::new("git")
-                .current_dir(&tmp_verus_dir)
-                .args(["checkout", &commit])
-                .status()?.success() {
-                Err("Failed to set Verus commit")?;
+            // Checkout the target branch if specified
+            if let Some(branch) = &branch {
+                if !Command::new("git")
+                    .current_dir(&tmp_verus_dir)
+                    .args(["fetch", "origin", branch])
+                    .status()?.success() {
+                    Err("Failed to set Verus branch")?;
+                }
+
+                if !Command::new("git")
+                    .current_dir(&tmp_verus_dir)
+                    .args(["checkout", branch])
+                    .status()?.success() {
+                    Err("Failed to set Verus branch")?;
+                }
+            }
+
+            // Checkout the target commit if specified
+            if let Some(commit) = &commit {
+                if !Command::new("git")
+                    .current_dir(&tmp_verus_dir)
+                    .args(["checkout", commit])
+                    .status()?.success() {
+                    Err("Failed to set Verus commit")?;
+                }
             }
         }
 
+        remote_repo_url = Some(remote_repo);
+        source = "remote";
         tmp_verus_dir.path().to_owned()
     };
 
-    build_verus(&verus_repo)?;
+    let release_dir = if prebuilt.is_some() {
+        verus_repo.join("release")
+    } else {
+        verus_repo.join("source").join("target-verus").join("release")
+    };
+
+    // Builds of remote commits are cached across installs
+    let cached_build = match (&remote_repo_url, build_cache_dir()) {
+        (Some(remote_repo), Some(cache_dir)) => {
+            let commit = git_output(&verus_repo, &["rev-parse", "HEAD"]).ok_or("Failed to get the Verus commit")?;
+            Some(cache_dir.join(build_cache_key(remote_repo, &commit, &build_args)?))
+        }
+        _ => None,
+    };
+
+    if let Some(prebuilt) = &prebuilt {
+        copy_prebuilt(Path::new(prebuilt), &release_dir)?;
+        if env::var("VERUS_Z3_VERSION").is_ok() || env::var("VERUS_Z3_SHA256").is_ok() {
+            check_z3(&verus_repo, &release_dir.join("z3"))?;
+        }
+        known_commit = prebuilt_commit(&release_dir);
+    } else {
+        match &cached_build {
+            Some(cached) if cached.is_dir() => {
+                println!("cargo:warning=Using the cached Verus build at {}", cached.display());
+                if release_dir.exists() {
+                    fs::remove_dir_all(&release_dir)?;
+                }
+                copy_dir(cached, &release_dir)?;
+            }
+            _ => {
+                build_verus(&verus_repo, &build_args)?;
+                if let Some(cached) = &cached_build {
+                    store_in_build_cache(&release_dir, cached)?;
+                }
+            }
+        }
+    }
+
+    // Record the Rust toolchain that Verus requires (vargo runs cargo with it)
+    let rust_toolchain = verus_repo.join("rust-toolchain.toml");
+    if prebuilt.is_none() && rust_toolchain.is_file() {
+        fs::copy(&rust_toolchain, release_dir.join("rust-toolchain.toml"))?;
+    }
+
+    if env::var("VERUS_SELF_TEST").as_deref() != Ok("off") {
+        self_test(&release_dir, &tmp_verus_dir.path().join("self-test"))?;
+    }
+
+    emit_provenance(&verus_repo, &release_dir, source, remote_repo_url.as_deref(), origin.as_deref(), known_commit.as_deref())?;
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    // Collect the sources of vstd/builtin/builtin_macros (and their path dependencies)
+    // with the same layout as in the Verus repo
+    let sources_dir = out_dir.join("verus-src");
+    if sources_dir.exists() {
+        fs::remove_dir_all(&sources_dir)?;
+    }
+    fs::create_dir_all(&sources_dir)?;
+
+    // Prebuilt releases do not contain the sources
+    if prebuilt.is_none() {
+        let canonical_repo = verus_repo.canonicalize()?;
+        for krate in VERUS_CRATES {
+            copy_crate_source(&canonical_repo, &canonical_repo.join("source").join(krate), &sources_dir)?;
+        }
+    }
+
+    // Finally, pack the compiled targets into OUT_DIR/verus.tar.gz
+    // with the crate sources under `src`
+    let archive_path = out_dir.join("verus.tar.gz");
+    pack_dirs(&[
+        (".", &release_dir),
+        ("src", &sources_dir),
+    ], &archive_path)?;
 
-    // Finally, copy the compiled targets to MANIFEST_DIR/target
-    let manifest_dir = env::var("CARGO_MANIFEST_DIR")?;
-    copy_dir(
-        &verus_repo.join("source").join("target-verus").join("release"),
-        &Path::new(&manifest_dir).join("target").join("verus"),
-    )?;
+    // Hash the archive so that vargo can cache
+    // the extracted copy under a content-addressed directory
+    let hash = Sha256::digest(fs::read(&archive_path)?);
+    println!("cargo:rustc-env=VARGO_EMBEDDED_HASH={:x}", hash);
 
     Ok(())
 }
@@ -0,0 +1,224 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::rustc_args::{Arg, Extern};
+
+/// The files Verus exports for a crate into `<dir>/verify`, named like rustc's output
+/// (`lib<crate name><extra filename>`)
+pub struct VerifyArtifacts {
+    pub verusdata: PathBuf,
+    pub rmeta: PathBuf,
+    /// Rustc arguments passed on to Verus in an argument file (for long command lines)
+    pub args: PathBuf,
+    /// Marks a crate compiled by vargo without verification (not depending on vstd,
+    /// or `verify = false`), which has no exports
+    unverified: PathBuf,
+}
+
+impl VerifyArtifacts {
+    /// `stem` is the crate name followed by its `-C extra-filename`
+    pub fn new(dir: &Path, stem: &str) -> VerifyArtifacts {
+        let verify_dir = dir.join("verify");
+        VerifyArtifacts {
+            verusdata: verify_dir.join(format!("{}.verusdata", stem)),
+            rmeta: verify_dir.join(format!("lib{}.rmeta", stem)),
+            args: verify_dir.join(format!("{}.args", stem)),
+            unverified: verify_dir.join(format!("{}.unverified", stem)),
+        }
+    }
+
+    fn exist(&self) -> bool {
+        self.verusdata.is_file() && self.rmeta.is_file()
+    }
+
+    /// Records whether the crate is compiled without verification, removing stale exports if it is
+    pub fn set_unverified(&self, unverified: bool) -> io::Result<()> {
+        if !unverified {
+            return match fs::remove_file(&self.unverified) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        for stale in [&self.verusdata, &self.rmeta] {
+            if stale.is_file() {
+                fs::remove_file(stale)?;
+            }
+        }
+        fs::create_dir_all(self.unverified.parent().unwrap_or(Path::new(".")))?;
+        fs::write(&self.unverified, "")
+    }
+}
+
+/// The stem of an extern crate's file (e.g. `a-0123abcd` for `liba-0123abcd.rlib`,
+/// `liba-0123abcd.so` or `a-0123abcd.dll`)
+fn extern_stem(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    Some(stem.strip_prefix("lib").unwrap_or(stem).to_string())
+}
+
+/// The name of the crate behind an extern, from its file (`lib<crate name>-<hash>.rlib`),
+/// which differs from the extern's name for renamed dependencies
+pub fn extern_crate_name(ext: &Extern) -> String {
+    ext.path.as_deref()
+        .and_then(|path| extern_stem(Path::new(path)))
+        .and_then(|stem| stem.split('-').next().map(str::to_string))
+        .unwrap_or_else(|| ext.name.clone())
+}
+
+/// The copy of a crate shipped with Verus in `dir` (e.g. `libvstd.rlib` or `libbuiltin_macros.so`)
+pub fn toolchain_crate(dir: &Path, crate_name: &str) -> Option<PathBuf> {
+    let stem = format!("lib{}", crate_name);
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .find(|path| {
+            path.file_stem().is_some_and(|file_stem| file_stem.to_str() == Some(stem.as_str()))
+                && path.extension().is_some_and(|ext| matches!(ext.to_str(), Some("rlib" | "so" | "dylib" | "dll")))
+        })
+}
+
+/// Directories of the `-L` arguments that rustc searches for crates
+pub fn crate_search_dirs(args: &[Arg]) -> Vec<PathBuf> {
+    args.iter()
+        .filter_map(|arg| match arg {
+            Arg::SearchPath { kind: None, path } => Some(PathBuf::from(path)),
+            Arg::SearchPath { kind: Some(kind), path } if matches!(kind.as_str(), "all" | "crate" | "dependency") => {
+                Some(PathBuf::from(path))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds the Verus exports of an extern crate, next to the extern's own file
+/// (which may be in a host or a target directory), then in the `-L` search directories
+pub fn resolve_extern(ext: &Extern, search_dirs: &[PathBuf]) -> Option<VerifyArtifacts> {
+    match &ext.path {
+        Some(path) => {
+            let path = Path::new(path);
+            let stem = extern_stem(path)?;
+            path.parent().into_iter()
+                .chain(search_dirs.iter().map(PathBuf::as_path))
+                .map(|dir| VerifyArtifacts::new(dir, &stem))
+                .find(VerifyArtifacts::exist)
+        }
+        // Without a path, rustc picks `lib<name>-*` from the search directories
+        None => search_dirs.iter().find_map(|dir| {
+            let prefix = format!("{}-", ext.name);
+            fs::read_dir(dir.join("verify")).ok()?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter_map(|name| name.strip_suffix(".verusdata").map(str::to_string))
+                .filter(|stem| stem.starts_with(&prefix) || *stem == ext.name)
+                .map(|stem| VerifyArtifacts::new(dir, &stem))
+                .find(VerifyArtifacts::exist)
+        }),
+    }
+}
+
+/// Whether the crate of an extern's file was compiled by vargo without verification
+pub fn is_unverified(path: &Path) -> bool {
+    let Some(stem) = extern_stem(path) else {
+        return false;
+    };
+    path.parent().is_some_and(|dir| VerifyArtifacts::new(dir, &stem).unverified.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ext;
+
+    /// Creates empty files at the given paths (relative to `dir`)
+    fn touch(dir: &Path, files: &[&str]) {
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+    }
+
+    #[test]
+    fn extern_crate_names() {
+        let cases = [
+            (ext(&[], "vstd", Some("/d/libvstd-0123abcd.rlib")), "vstd"),
+            // A renamed dependency (`my_vstd = { package = "vstd", ... }`)
+            (ext(&[], "my_vstd", Some("/d/libvstd-0123abcd.rmeta")), "vstd"),
+            (ext(&[], "builtin_macros", Some("/d/libbuiltin_macros-0123abcd.so")), "builtin_macros"),
+            (ext(&[], "a", Some("/d/a-0123abcd.dll")), "a"),
+            (ext(&[], "a", None), "a"),
+        ];
+
+        for (ext, expected) in cases {
+            assert_eq!(extern_crate_name(&ext), expected, "crate name of {:?}", ext.value());
+        }
+    }
+
+    #[test]
+    fn toolchain_crates() {
+        let dir = tempdir::TempDir::new("vargo-toolchain").unwrap();
+        touch(dir.path(), &["libvstd.rlib", "libvstd.vir", "libbuiltin_macros.so", "libbuiltin.txt", "libvstd_extra.rlib"]);
+
+        let cases = [
+            ("vstd", Some("libvstd.rlib")),
+            ("builtin_macros", Some("libbuiltin_macros.so")),
+            ("builtin", None),
+            ("missing", None),
+        ];
+
+        for (crate_name, expected) in cases {
+            assert_eq!(
+                toolchain_crate(dir.path(), crate_name),
+                expected.map(|file| dir.path().join(file)),
+                "toolchain crate {}", crate_name,
+            );
+        }
+    }
+
+    #[test]
+    fn search_dirs() {
+        let args = crate::rustc_args::parse(&[
+            "-L", "dependency=/deps", "-L", "native=/native", "-L", "/plain", "-Lcrate=/crate", "-L", "framework=/fw",
+        ].map(str::to_string)).unwrap();
+        assert_eq!(crate_search_dirs(&args), ["/deps", "/plain", "/crate"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn resolved_externs() {
+        let dir = tempdir::TempDir::new("vargo-externs").unwrap();
+        let (host, target, other) = (dir.path().join("host"), dir.path().join("target"), dir.path().join("other"));
+        touch(dir.path(), &[
+            // Exported next to the extern's own file
+            "host/liba-1.rlib", "host/verify/a-1.verusdata", "host/verify/liba-1.rmeta",
+            // Exported in a search directory only
+            "host/libb-2.rlib", "target/verify/b-2.verusdata", "target/verify/libb-2.rmeta",
+            // Exported in both search directories
+            "target/verify/c-3.verusdata", "target/verify/libc-3.rmeta",
+            "other/verify/c-4.verusdata", "other/verify/libc-4.rmeta",
+            // Incomplete exports
+            "host/libd-5.rlib", "host/verify/d-5.verusdata",
+        ]);
+        let search_dirs = [target.clone(), other.clone()];
+
+        let cases = [
+            (ext(&[], "a", host.join("liba-1.rlib").to_str()), Some(host.join("verify/a-1.verusdata"))),
+            // A renamed dependency is found from its file
+            (ext(&[], "renamed_a", host.join("liba-1.rlib").to_str()), Some(host.join("verify/a-1.verusdata"))),
+            (ext(&[], "b", host.join("libb-2.rlib").to_str()), Some(target.join("verify/b-2.verusdata"))),
+            // Without a path, the first search directory with exports wins
+            (ext(&[], "c", None), Some(target.join("verify/c-3.verusdata"))),
+            (ext(&[], "d", host.join("libd-5.rlib").to_str()), None),
+            (ext(&[], "e", None), None),
+        ];
+
+        for (ext, expected) in cases {
+            let resolved = resolve_extern(&ext, &search_dirs).map(|artifacts| artifacts.verusdata);
+            assert_eq!(resolved, expected, "resolving {:?}", ext.value());
+        }
+
+        // The search directories are tried in order
+        let reversed = [other.clone(), target];
+        let resolved = resolve_extern(&ext(&[], "c", None), &reversed).map(|artifacts| artifacts.verusdata);
+        assert_eq!(resolved, Some(other.join("verify/c-4.verusdata")));
+    }
+}
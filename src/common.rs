@@ -0,0 +1,32 @@
+// Definitions shared with build.rs, which `include!`s this file
+// (so it cannot have inner attributes or `use` declarations)
+
+/// Default Verus repo (used when the Verus toolchain was not built from a remote repo)
+pub const DEFAULT_VERUS_REPO: &str = "https://github.com/verus-lang/verus.git";
+
+/// Crates shipped with Verus that a verified crate depends on, whose sources are
+/// shipped with the toolchain (so that vargo can patch the git dependencies on them)
+pub const VERUS_CRATES: [&str; 3] = ["vstd", "builtin", "builtin_macros"];
+
+/// Recursively copy a directory, keeping symlinks as symlinks
+pub fn copy_dir(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_dir(&src_path, &dst_path)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(&src_path)?, &dst_path)?;
+        } else {
+            // fs::copy also copies the permission bits
+            std::fs::copy(&src_path, &dst_path)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::cargo_warning;
+
+/// The Verus settings of a Cargo.toml
+#[derive(Default)]
+pub struct Settings {
+    /// `[package.metadata.verus]`, over the deprecated `[verus]` table
+    package: toml::Table,
+    /// `[workspace.metadata.verus]`
+    workspace: toml::Table,
+    /// Whether the deprecated `[verus]` table is used
+    pub deprecated: bool,
+    /// Whether the manifest has a `[workspace]` table (i.e. it is a workspace root)
+    pub is_workspace_root: bool,
+    /// Whether the manifest has a `[package]` table
+    pub is_package: bool,
+}
+
+impl Settings {
+    /// A setting of the package, falling back to the workspace
+    pub fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.package.get(key).or_else(|| self.workspace.get(key))
+    }
+
+    /// Additional Verus flags, where the package's override the workspace's (see [`merge_flags`])
+    pub fn extra_flags(&self, manifest: &Path) -> anyhow::Result<Vec<Vec<String>>> {
+        let mut flags = Vec::new();
+        for table in [&self.workspace, &self.package] {
+            if let Some(value) = table.get("extra_flags") {
+                let value = value.as_str()
+                    .with_context(|| format!("Expected a string for `extra_flags` in {}", manifest.display()))?;
+                flags.push(shell_words::split(value)
+                    .with_context(|| format!("Invalid `extra_flags` in {}", manifest.display()))?);
+            }
+        }
+        Ok(flags)
+    }
+
+    /// The Verus tables of the manifest (e.g. for validation)
+    pub fn tables(&self) -> [&toml::Table; 2] {
+        [&self.package, &self.workspace]
+    }
+}
+
+/// A table at `path` (e.g. `["package", "metadata", "verus"]`) in a manifest
+fn table_at(manifest: &toml::Table, path: &[&str], file: &Path) -> anyhow::Result<Option<toml::Table>> {
+    let mut value = None;
+    let mut table = manifest;
+    for (i, key) in path.iter().enumerate() {
+        let Some(next) = table.get(*key) else {
+            return Ok(None);
+        };
+        let next = next.as_table()
+            .with_context(|| format!("`{}` in {} is not a table", path[..=i].join("."), file.display()))?;
+        value = Some(next);
+        table = next;
+    }
+    Ok(value.cloned())
+}
+
+/// Reads the Verus settings of a Cargo.toml
+pub fn read_settings(manifest: &Path) -> anyhow::Result<Settings> {
+    let content = fs::read_to_string(manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let parsed = toml::from_str::<toml::Table>(&content)
+        .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+
+    let legacy = table_at(&parsed, &["verus"], manifest)?;
+    let deprecated = legacy.is_some();
+    let mut package = legacy.unwrap_or_default();
+    package.extend(table_at(&parsed, &["package", "metadata", "verus"], manifest)?.unwrap_or_default());
+
+    Ok(Settings {
+        package,
+        workspace: table_at(&parsed, &["workspace", "metadata", "verus"], manifest)?.unwrap_or_default(),
+        deprecated,
+        is_workspace_root: parsed.contains_key("workspace"),
+        is_package: parsed.contains_key("package"),
+    })
+}
+
+/// Reads the Verus settings of a crate, with the `[workspace.metadata.verus]` of
+/// the root of the workspace containing it as defaults
+pub fn crate_settings(crate_dir: &Path) -> anyhow::Result<Settings> {
+    let mut settings = read_settings(&crate_dir.join("Cargo.toml"))?;
+
+    // Like cargo, the closest manifest with a `[workspace]` table is the workspace root
+    if !settings.is_workspace_root {
+        let root = crate_dir.ancestors().skip(1)
+            .map(|dir| dir.join("Cargo.toml"))
+            .filter(|manifest| manifest.is_file())
+            .filter_map(|manifest| read_settings(&manifest).ok())
+            .find(|root| root.is_workspace_root);
+        if let Some(root) = root {
+            settings.workspace = root.workspace;
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Warns about a deprecated `[verus]` table in `manifest`
+pub fn warn_deprecated(manifest: &Path) {
+    cargo_warning(&format!(
+        "the `[verus]` table in {} is deprecated, move it to `[package.metadata.verus]` \
+        (or `[workspace.metadata.verus]` in a virtual workspace root)",
+        manifest.display(),
+    ));
+}
+
+/// Merges Verus flags from the lowest to the highest precedence
+///
+/// An option (with the values following it) given by a source replaces all the occurrences
+/// of the same option from the sources before it.
+pub fn merge_flags(sources: &[Vec<String>]) -> Vec<String> {
+    let mut merged: Vec<(String, Vec<String>)> = Vec::new();
+
+    for flags in sources {
+        // Group each option with its values, e.g. `--rlimit 10`
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        for flag in flags {
+            match groups.last_mut() {
+                Some((_, group)) if !flag.starts_with('-') => group.push(flag.clone()),
+                _ => {
+                    let name = flag.split('=').next().unwrap_or_default().to_string();
+                    groups.push((name, vec![flag.clone()]));
+                }
+            }
+        }
+
+        merged.retain(|(name, _)| !groups.iter().any(|(other, _)| other == name));
+        merged.extend(groups);
+    }
+
+    merged.into_iter().flat_map(|(_, group)| group).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_flags_precedence() {
+        let cases: &[(&[&str], &str)] = &[
+            (&[], ""),
+            (&["--rlimit 10"], "--rlimit 10"),
+            (&["--rlimit 10", "--rlimit 20"], "--rlimit 20"),
+            (&["--rlimit 10", "--expand-errors", "--rlimit=30"], "--expand-errors --rlimit=30"),
+            (&["--rlimit 10 --expand-errors", "--rlimit 20"], "--expand-errors --rlimit 20"),
+            (&["--verify-module a --verify-module b", "--verify-module c"], "--verify-module c"),
+            (&["--verify-module a --verify-module b", ""], "--verify-module a --verify-module b"),
+            (&["--triggers-mode silent", "--rlimit 5", "--no-lifetime"], "--triggers-mode silent --rlimit 5 --no-lifetime"),
+        ];
+
+        for (sources, expected) in cases {
+            let sources: Vec<Vec<String>> = sources.iter().map(|flags| shell_words::split(flags).unwrap()).collect();
+            assert_eq!(merge_flags(&sources), shell_words::split(expected).unwrap(), "merging {:?}", sources);
+        }
+    }
+
+    #[test]
+    fn crate_settings_sources() {
+        let dir = tempdir::TempDir::new("vargo-settings").unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+
+        write("Cargo.toml", "[workspace]\nmembers = [\"*\"]\n\n[workspace.metadata.verus]\nverify = false\nextra_flags = \"--rlimit 10\"\n");
+        write("member/Cargo.toml", "[package]\nname = \"member\"\n\n[package.metadata.verus]\nextra_flags = \"--rlimit 20\"\n");
+        write("legacy/Cargo.toml", "[package]\nname = \"legacy\"\n\n[verus]\nverify = true\nextra_flags = \"--expand-errors\"\n\n[package.metadata.verus]\nextra_flags = \"--rlimit 30\"\n");
+        // A nested workspace root is the closest one, so the outer workspace settings do not apply
+        write("nested/Cargo.toml", "[package]\nname = \"nested\"\n\n[workspace]\n");
+        write("nested/inner/Cargo.toml", "[package]\nname = \"inner\"\n");
+
+        // (crate, `verify`, flags from the lowest to the highest precedence, deprecated)
+        let cases: &[(&str, Option<bool>, &[&str], bool)] = &[
+            ("", Some(false), &["--rlimit 10"], false),
+            ("member", Some(false), &["--rlimit 10", "--rlimit 20"], false),
+            ("legacy", Some(true), &["--rlimit 10", "--rlimit 30"], true),
+            ("nested", None, &[], false),
+            ("nested/inner", None, &[], false),
+        ];
+
+        for (krate, verify, flags, deprecated) in cases {
+            let crate_dir = dir.path().join(krate);
+            let settings = crate_settings(&crate_dir).unwrap();
+            let manifest = crate_dir.join("Cargo.toml");
+            let expected: Vec<Vec<String>> = flags.iter().map(|flags| shell_words::split(flags).unwrap()).collect();
+            assert_eq!(settings.get("verify").and_then(toml::Value::as_bool), *verify, "`verify` of {:?}", krate);
+            assert_eq!(settings.extra_flags(&manifest).unwrap(), expected, "flags of {:?}", krate);
+            assert_eq!(settings.deprecated, *deprecated, "deprecation of {:?}", krate);
+        }
+    }
+}
@@ -0,0 +1,671 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+
+use crate::common::{DEFAULT_VERUS_REPO, VERUS_CRATES};
+use crate::config;
+use crate::toolchain::{self, EMBEDDED_TOOLCHAIN};
+use crate::version::EMBEDDED_PROVENANCE;
+use crate::{cargo_message, cargo_warning, Level};
+
+/// Path to the root Cargo.toml of the workspace containing `manifest_path`
+/// (or the current directory)
+pub fn workspace_manifest(manifest_path: Option<&Path>) -> anyhow::Result<PathBuf> {
+    let output = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .args(manifest_path.into_iter().flat_map(|path| [Path::new("--manifest-path"), path]))
+        .output()
+        .context("Failed to run cargo locate-project")?;
+    if !output.status.success() {
+        bail!("Failed to locate the workspace: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+}
+
+/// The `--manifest-path` given in cargo arguments (before any `--`), if any
+pub fn manifest_path_arg(cargo_args: &[String]) -> Option<PathBuf> {
+    let mut iter = cargo_args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = iter.next() {
+        if arg == "--manifest-path" {
+            return iter.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--manifest-path=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Where a Verus crate locked in Cargo.lock comes from
+#[derive(Debug, PartialEq, Eq)]
+enum LockedSource {
+    /// `git+<url>[?<branch/rev/tag>]#<commit>`
+    Git { url: String, rev: String },
+    /// `registry+<url>` or `sparse+<url>`, with the locked version
+    Registry { version: String },
+    /// A path dependency (without a `source`)
+    Path,
+    /// Any other source
+    Other(String),
+}
+
+/// A Verus crate locked in Cargo.lock
+#[derive(Debug, PartialEq, Eq)]
+struct LockedCrate {
+    name: String,
+    source: LockedSource,
+}
+
+/// Reads the Verus crates locked in the contents of a Cargo.lock
+fn parse_locked_verus_crates(content: &str) -> anyhow::Result<Vec<LockedCrate>> {
+    let parsed = toml::from_str::<toml::Value>(content)?;
+
+    let mut crates = Vec::new();
+    let packages = parsed.get("package").and_then(|packages| packages.as_array());
+
+    for package in packages.into_iter().flatten() {
+        let Some(name) = package.get("name").and_then(|name| name.as_str()) else {
+            continue;
+        };
+        if !VERUS_CRATES.contains(&name) {
+            continue;
+        }
+
+        let version = package.get("version").and_then(|version| version.as_str()).unwrap_or_default();
+        let source = match package.get("source").and_then(|source| source.as_str()) {
+            None => LockedSource::Path,
+            Some(source) => match source.strip_prefix("git+").and_then(|source| source.rsplit_once('#')) {
+                Some((url, rev)) => LockedSource::Git {
+                    url: url.split_once('?').map_or(url, |(url, _)| url).to_string(),
+                    rev: rev.to_string(),
+                },
+                None if source.starts_with("registry+") || source.starts_with("sparse+") => {
+                    LockedSource::Registry { version: version.to_string() }
+                }
+                None => LockedSource::Other(source.to_string()),
+            },
+        };
+
+        crates.push(LockedCrate { name: name.to_string(), source });
+    }
+
+    Ok(crates)
+}
+
+/// Reads the Verus crates locked in a Cargo.lock
+fn locked_verus_crates(lock_path: &Path) -> anyhow::Result<Vec<LockedCrate>> {
+    let content = fs::read_to_string(lock_path)
+        .with_context(|| format!("Failed to read {}", lock_path.display()))?;
+    parse_locked_verus_crates(&content)
+        .with_context(|| format!("Failed to parse {}", lock_path.display()))
+}
+
+/// What to do when the locked vstd does not match the selected Verus
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VstdCheck {
+    Off,
+    Warn,
+    Error,
+}
+
+impl VstdCheck {
+    fn parse(s: &str) -> anyhow::Result<VstdCheck> {
+        match s {
+            "off" => Ok(VstdCheck::Off),
+            "warn" => Ok(VstdCheck::Warn),
+            "error" => Ok(VstdCheck::Error),
+            _ => bail!("Invalid vstd check mode `{}` (expected `off`, `warn` or `error`)", s),
+        }
+    }
+}
+
+/// Reads the vstd check mode from `VARGO_VSTD_CHECK`, or from
+/// `vstd_check` in the Verus settings of the workspace's Cargo.toml (default `warn`)
+pub fn vstd_check_mode(manifest: &Path) -> anyhow::Result<VstdCheck> {
+    if let Ok(mode) = env::var("VARGO_VSTD_CHECK") {
+        return VstdCheck::parse(&mode).context("Invalid VARGO_VSTD_CHECK");
+    }
+
+    match config::read_settings(manifest)?.get("vstd_check") {
+        Some(mode) => VstdCheck::parse(mode.as_str().unwrap_or_default())
+            .with_context(|| format!("Invalid `vstd_check` in {}", manifest.display())),
+        None => Ok(VstdCheck::Warn),
+    }
+}
+
+/// Describes the Verus crates locked in `lock_path` that do not match the Verus at `commit`
+/// (with the release `version`, if known): git revisions other than `commit`, registry versions
+/// other than `version` (or any if it is unknown), and path dependencies unless `local_vstd` is set
+/// (i.e. they are patched to the sources shipped with the toolchain)
+pub fn vstd_mismatches(lock_path: &Path, commit: &str, version: Option<&str>, local_vstd: bool) -> anyhow::Result<Vec<String>> {
+    Ok(locked_verus_crates(lock_path)?
+        .into_iter()
+        .filter_map(|locked| match locked.source {
+            LockedSource::Git { url, rev } => {
+                (rev != commit).then(|| format!("{} is locked to {}#{}", locked.name, url, rev))
+            }
+            LockedSource::Registry { version: locked_version } => match version {
+                Some(version) if version == locked_version => None,
+                Some(_) => Some(format!("{} is locked to version {} from a registry", locked.name, locked_version)),
+                None => Some(format!(
+                    "{} is locked to version {} from a registry, which cannot be checked against a commit",
+                    locked.name, locked_version,
+                )),
+            },
+            LockedSource::Path => (!local_vstd).then(|| format!(
+                "{} is a path dependency, which cannot be checked against a commit", locked.name,
+            )),
+            LockedSource::Other(source) => Some(format!("{} comes from {}", locked.name, source)),
+        })
+        .collect())
+}
+
+/// Compares the sources of vstd/builtin/builtin_macros locked in the workspace's
+/// Cargo.lock against the commit (and release version) of the selected Verus toolchain
+/// (skipped if its commit is unknown)
+///
+/// Otherwise exec code would be compiled against one vstd while proofs are checked
+/// against the one built into Verus.
+pub fn check_vstd_revision(toolchain_name: Option<&str>, toolchain_dir: &Path, cargo_args: &[String]) -> anyhow::Result<()> {
+    let Some(commit) = toolchain::selected_toolchain_commit(toolchain_name, toolchain_dir) else {
+        return Ok(());
+    };
+
+    // Not in a cargo project, let cargo report that
+    let Ok(manifest) = workspace_manifest(manifest_path_arg(cargo_args).as_deref()) else {
+        return Ok(());
+    };
+
+    let mode = vstd_check_mode(&manifest)?;
+    if mode == VstdCheck::Off {
+        return Ok(());
+    }
+
+    // Cargo.lock does not exist before the first build
+    let lock_path = manifest.with_file_name("Cargo.lock");
+    if !lock_path.exists() {
+        return Ok(());
+    }
+
+    let version = toolchain::toolchain_version(toolchain_dir);
+    let (local_vstd, _) = local_vstd_mode(&manifest, cargo_args)?;
+    let mismatches = vstd_mismatches(&lock_path, &commit, version.as_deref(), local_vstd)?;
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let mut msg = format!(
+        "Verus dependencies locked in {} do not match the selected Verus (commit {}):",
+        lock_path.display(), commit,
+    );
+    for mismatch in &mismatches {
+        msg.push_str(&format!("\n    {}", mismatch));
+    }
+    msg.push_str(&format!(
+        "\nExec code would be compiled against a different vstd than the one proofs are checked against; \
+        run `vargo fix-deps` to pin them to `rev = \"{}\"` \
+        (set `vstd_check = \"off\"` in `[workspace.metadata.verus]` of the workspace's Cargo.toml to disable this check)",
+        commit,
+    ));
+
+    match mode {
+        VstdCheck::Error => bail!(msg),
+        _ => {
+            cargo_warning(&msg);
+            Ok(())
+        }
+    }
+}
+
+/// Dependency tables of a manifest (including target-specific ones)
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Git source of the Verus crates matching a Verus toolchain
+pub struct GitSource {
+    pub url: String,
+    /// Commit to pin the Verus crates to (`None` if the toolchain's commit is unknown)
+    pub rev: Option<String>,
+}
+
+/// Git URL and commit of the selected Verus toolchain, which the Verus crates should be pinned to:
+/// the provenance recorded at build time for the embedded toolchain,
+/// and the `version.json` of official releases otherwise
+///
+/// Warns if the commit is unknown, in which case the Verus crates are not pinned.
+pub fn verus_git_source(toolchain_name: Option<&str>, verus_path: &Path) -> GitSource {
+    let repo = if toolchain_name == Some(EMBEDDED_TOOLCHAIN) { EMBEDDED_PROVENANCE.repo } else { "" };
+    let commit = verus_path.parent()
+        .and_then(|toolchain_dir| toolchain::selected_toolchain_commit(toolchain_name, toolchain_dir));
+
+    if commit.is_none() {
+        cargo_warning(&format!(
+            "the commit of the Verus at {} is unknown, so the Verus dependencies are not pinned to a revision",
+            verus_path.display(),
+        ));
+    }
+
+    let is_url = repo.contains("://") || repo.starts_with("git@");
+    GitSource {
+        url: if is_url { repo } else { DEFAULT_VERUS_REPO }.to_string(),
+        rev: commit,
+    }
+}
+
+/// A workspace member, as reported by `cargo metadata`
+pub struct Member {
+    pub name: String,
+    pub manifest_path: PathBuf,
+}
+
+/// Output of `cargo metadata --no-deps` for the workspace containing `manifest_path`
+/// (or the current directory)
+pub fn workspace_metadata(manifest_path: Option<&Path>) -> anyhow::Result<serde_json::Value> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .args(manifest_path.into_iter().flat_map(|path| [Path::new("--manifest-path"), path]))
+        .output()
+        .context("Failed to run cargo metadata")?;
+    if !output.status.success() {
+        bail!("Failed to get workspace metadata: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Lists the members of the workspace containing `manifest_path` (or the current directory)
+/// and the path to the workspace root manifest
+pub fn workspace_members(manifest_path: Option<&Path>) -> anyhow::Result<(Vec<Member>, PathBuf)> {
+    let metadata = workspace_metadata(manifest_path)?;
+    let root = metadata["workspace_root"].as_str()
+        .context("Missing workspace_root in cargo metadata")?;
+
+    let mut members = Vec::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        let (Some(name), Some(manifest_path)) = (package["name"].as_str(), package["manifest_path"].as_str()) else {
+            bail!("Unexpected package entry in cargo metadata");
+        };
+        members.push(Member { name: name.to_string(), manifest_path: PathBuf::from(manifest_path) });
+    }
+
+    Ok((members, Path::new(root).join("Cargo.toml")))
+}
+
+/// Pins the Verus git dependencies in a dependency table to `url` and `rev`,
+/// returning the names of the updated dependencies
+///
+/// Dependencies inherited from the workspace or not coming from git are left unchanged
+/// (with a warning for the latter).
+fn pin_dependency_table(table: &mut dyn toml_edit::TableLike, url: &str, rev: &str) -> Vec<String> {
+    let mut pinned = Vec::new();
+
+    for (key, item) in table.iter_mut() {
+        let Some(dep) = item.as_table_like_mut() else {
+            // A version requirement (`vstd = "..."`)
+            if VERUS_CRATES.contains(&key.get()) {
+                cargo_warning(&format!("`{}` is not a git dependency, leaving it unchanged", key.get()));
+            }
+            continue;
+        };
+
+        // Dependencies may be renamed with `package = "..."`
+        let package = dep.get("package").and_then(|package| package.as_str()).unwrap_or(key.get());
+        if !VERUS_CRATES.contains(&package) || dep.contains_key("workspace") {
+            continue;
+        }
+
+        if !dep.contains_key("git") {
+            cargo_warning(&format!("`{}` is not a git dependency, leaving it unchanged", key.get()));
+            continue;
+        }
+
+        let up_to_date = dep.get("git").and_then(|git| git.as_str()) == Some(url)
+            && dep.get("rev").and_then(|rev| rev.as_str()) == Some(rev)
+            && !dep.contains_key("branch")
+            && !dep.contains_key("tag");
+
+        if !up_to_date {
+            dep.remove("branch");
+            dep.remove("tag");
+            dep.insert("git", toml_edit::value(url));
+            dep.insert("rev", toml_edit::value(rev));
+            pinned.push(key.get().to_string());
+        }
+    }
+
+    pinned
+}
+
+/// Names of the Verus crates a dependency table already depends on (possibly renamed)
+fn present_verus_crates(table: &dyn toml_edit::TableLike) -> Vec<String> {
+    table.iter()
+        .filter_map(|(key, item)| {
+            let package = item.as_table_like()
+                .and_then(|dep| dep.get("package"))
+                .and_then(|package| package.as_str())
+                .unwrap_or(key);
+            VERUS_CRATES.contains(&package).then(|| package.to_string())
+        })
+        .collect()
+}
+
+const FIX_DEPS_USAGE: &str = "\
+Usage: vargo fix-deps [--add] [-p <package>]...
+
+Pins the vstd, builtin and builtin_macros git dependencies of all workspace members
+(or only the given packages) to the commit of the selected Verus toolchain.
+
+Options:
+    --add                    Also add the Verus dependencies to packages missing them
+    -p, --package <package>  Only fix the given package (can be repeated)";
+
+/// Entry point of `vargo fix-deps ...`, pinning to the toolchain `toolchain_name` (if any)
+/// with the `verus` executable at `verus_path`
+pub fn fix_deps_command(args: &[String], toolchain_name: Option<&str>, verus_path: &Path) -> anyhow::Result<i32> {
+    let mut add = false;
+    let mut packages = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--add" => add = true,
+            "-p" | "--package" => packages.push(iter.next().context("--package expects a value")?.as_str()),
+            "-h" | "--help" => {
+                println!("{}", FIX_DEPS_USAGE);
+                return Ok(0);
+            }
+            _ => bail!("Unexpected argument `{}`\n\n{}", arg, FIX_DEPS_USAGE),
+        }
+    }
+
+    let source = verus_git_source(toolchain_name, verus_path);
+    pin_verus_dependencies(None, &packages, add, &source)?;
+    Ok(0)
+}
+
+/// Pins the Verus git dependencies of the given packages (or all members if empty) of the
+/// workspace containing `manifest_path` (or the current directory) to `source`,
+/// adding them if `add` is set
+///
+/// Without a commit in `source`, existing dependencies are left unchanged and added ones are unpinned.
+pub fn pin_verus_dependencies(
+    manifest_path: Option<&Path>,
+    packages: &[&str],
+    add: bool,
+    source: &GitSource,
+) -> anyhow::Result<()> {
+    let url = source.url.as_str();
+    let (members, root_manifest) = workspace_members(manifest_path)?;
+
+    for package in packages {
+        if !members.iter().any(|member| member.name == *package) {
+            bail!("Package `{}` is not a member of the workspace", package);
+        }
+    }
+    let selected: Vec<_> = members.iter()
+        .filter(|member| packages.is_empty() || packages.contains(&member.name.as_str()))
+        .collect();
+
+    // The workspace root may or may not be a package itself
+    // (and it goes first for [workspace.dependencies] to be known)
+    let mut manifests: Vec<&Path> = vec![&root_manifest];
+    manifests.extend(selected.iter()
+        .map(|member| member.manifest_path.as_path())
+        .filter(|manifest| *manifest != root_manifest));
+
+    // Verus crates declared in [workspace.dependencies] (which members can inherit)
+    let mut workspace_crates = Vec::new();
+
+    for manifest in manifests {
+        let content = fs::read_to_string(manifest)
+            .with_context(|| format!("Failed to read {}", manifest.display()))?;
+        let mut doc = content.parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+
+        let mut pinned = Vec::new();
+        let mut added = Vec::new();
+
+        if manifest == root_manifest {
+            if let Some(table) = doc.get_mut("workspace")
+                .and_then(|workspace| workspace.get_mut("dependencies"))
+                .and_then(|deps| deps.as_table_like_mut())
+            {
+                if let Some(rev) = &source.rev {
+                    pinned.extend(pin_dependency_table(table, url, rev));
+                }
+                workspace_crates = present_verus_crates(table);
+            }
+        }
+
+        let is_selected = selected.iter().any(|member| member.manifest_path == manifest);
+        if is_selected {
+            if let Some(rev) = &source.rev {
+                for name in DEPENDENCY_TABLES {
+                    if let Some(table) = doc.get_mut(name).and_then(|deps| deps.as_table_like_mut()) {
+                        pinned.extend(pin_dependency_table(table, url, rev));
+                    }
+                }
+
+                if let Some(targets) = doc.get_mut("target").and_then(|targets| targets.as_table_like_mut()) {
+                    for (_, target) in targets.iter_mut() {
+                        for name in DEPENDENCY_TABLES {
+                            if let Some(table) = target.get_mut(name).and_then(|deps| deps.as_table_like_mut()) {
+                                pinned.extend(pin_dependency_table(table, url, rev));
+                            }
+                        }
+                    }
+                }
+            }
+
+            if add {
+                let deps = doc.entry("dependencies")
+                    .or_insert_with(toml_edit::table)
+                    .as_table_like_mut()
+                    .with_context(|| format!("`dependencies` is not a table in {}", manifest.display()))?;
+                let present = present_verus_crates(deps);
+
+                for name in VERUS_CRATES {
+                    if present.iter().any(|present| present == name) {
+                        continue;
+                    }
+
+                    let mut dep = toml_edit::InlineTable::new();
+                    if workspace_crates.iter().any(|krate| krate == name) {
+                        dep.insert("workspace", true.into());
+                    } else {
+                        dep.insert("git", url.into());
+                        if let Some(rev) = &source.rev {
+                            dep.insert("rev", rev.as_str().into());
+                        }
+                    }
+                    deps.insert(name, toml_edit::value(dep));
+                    added.push(name.to_string());
+                }
+            }
+        }
+
+        if pinned.is_empty() && added.is_empty() {
+            continue;
+        }
+
+        fs::write(manifest, doc.to_string())
+            .with_context(|| format!("Failed to write {}", manifest.display()))?;
+
+        let mut changes = Vec::new();
+        if !pinned.is_empty() {
+            changes.push(format!("pinned {}", pinned.join(", ")));
+        }
+        if !added.is_empty() {
+            changes.push(format!("added {}", added.join(", ")));
+        }
+        cargo_message(Level::Note, "Updated", &format!("{} ({})", manifest.display(), changes.join("; ")));
+    }
+
+    Ok(())
+}
+
+/// Parses a boolean setting (`true`/`1` or `false`/`0`)
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether `vstd`, `builtin` and `builtin_macros` should be patched to the sources shipped
+/// with the toolchain: `VARGO_LOCAL_VSTD`, then `local_vstd` in the Verus settings of the
+/// workspace's Cargo.toml, and otherwise only for offline builds
+///
+/// Returns `(enabled, explicitly requested)`.
+pub fn local_vstd_mode(manifest: &Path, cargo_args: &[String]) -> anyhow::Result<(bool, bool)> {
+    if let Ok(value) = env::var("VARGO_LOCAL_VSTD") {
+        let enabled = parse_bool(&value).context("Invalid VARGO_LOCAL_VSTD (expected `true` or `false`)")?;
+        return Ok((enabled, true));
+    }
+
+    if let Some(value) = config::read_settings(manifest)?.get("local_vstd") {
+        let enabled = value.as_bool()
+            .with_context(|| format!("Expected a boolean for `local_vstd` in {}", manifest.display()))?;
+        return Ok((enabled, true));
+    }
+
+    let offline = cargo_args.iter().any(|arg| arg == "--offline" || arg == "--frozen")
+        || env::var("CARGO_NET_OFFLINE").ok().as_deref().and_then(parse_bool) == Some(true);
+    Ok((offline, false))
+}
+
+/// Builds `--config` arguments for cargo that `[patch]` the git dependencies on `vstd`,
+/// `builtin` and `builtin_macros` with the sources shipped in `toolchain_dir/src`
+///
+/// Only the git URLs (and crates) the workspace members actually depend on are patched,
+/// since cargo warns about unused patches.
+pub fn local_vstd_patches(toolchain_dir: &Path, cargo_args: &[String]) -> anyhow::Result<Vec<String>> {
+    // Not in a cargo project, let cargo report that
+    let manifest_path = manifest_path_arg(cargo_args);
+    let Ok(manifest) = workspace_manifest(manifest_path.as_deref()) else {
+        return Ok(Vec::new());
+    };
+
+    let (enabled, explicit) = local_vstd_mode(&manifest, cargo_args)?;
+    if !enabled {
+        return Ok(Vec::new());
+    }
+
+    // Collect (git URL, crate) pairs of the direct Verus dependencies of workspace members
+    let metadata = workspace_metadata(manifest_path.as_deref())?;
+    let mut patches = Vec::new();
+
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        for dep in package["dependencies"].as_array().into_iter().flatten() {
+            let (Some(name), Some(source)) = (dep["name"].as_str(), dep["source"].as_str()) else {
+                continue;
+            };
+            let Some(url) = source.strip_prefix("git+") else {
+                continue;
+            };
+            let url = url.split_once('?').map_or(url, |(url, _)| url);
+            let url = url.split_once('#').map_or(url, |(url, _)| url);
+
+            if VERUS_CRATES.contains(&name) && !patches.contains(&(url, name)) {
+                patches.push((url, name));
+            }
+        }
+    }
+
+    if patches.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let sources_dir = toolchain_dir.join("src").join("source");
+    if !VERUS_CRATES.iter().all(|krate| sources_dir.join(krate).join("Cargo.toml").is_file()) {
+        let msg = format!("The Verus toolchain at {} does not ship the sources of vstd", toolchain_dir.display());
+        if explicit {
+            bail!(msg);
+        }
+        cargo_warning(&format!("{}, not patching the Verus dependencies for the offline build", msg));
+        return Ok(Vec::new());
+    }
+
+    let mut config_args = Vec::new();
+    for (url, name) in patches {
+        let path = sources_dir.join(name);
+        let path = path.to_str().context("Invalid character in toolchain path")?;
+        config_args.push("--config".to_string());
+        config_args.push(format!(
+            "patch.{}.{}.path={}",
+            toml::Value::String(url.to_string()),
+            name,
+            toml::Value::String(path.to_string()),
+        ));
+    }
+
+    Ok(config_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_sources() {
+        let git = |rev: &str| LockedSource::Git { url: "https://github.com/verus-lang/verus.git".to_string(), rev: rev.to_string() };
+        let registry = || LockedSource::Registry { version: "0.0.1".to_string() };
+        let cases = [
+            ("source = \"git+https://github.com/verus-lang/verus.git#0123abcd\"", git("0123abcd")),
+            ("source = \"git+https://github.com/verus-lang/verus.git?rev=0123#0123abcd\"", git("0123abcd")),
+            ("source = \"git+https://github.com/verus-lang/verus.git?branch=main#4567\"", git("4567")),
+            ("source = \"registry+https://github.com/rust-lang/crates.io-index\"", registry()),
+            ("source = \"sparse+https://index.crates.io/\"", registry()),
+            ("", LockedSource::Path),
+            ("source = \"vendored\"", LockedSource::Other("vendored".to_string())),
+        ];
+
+        for (source, expected) in cases {
+            let lock = format!("version = 4\n\n[[package]]\nname = \"vstd\"\nversion = \"0.0.1\"\n{}\n", source);
+            let expected = LockedCrate { name: "vstd".to_string(), source: expected };
+            assert_eq!(parse_locked_verus_crates(&lock).unwrap(), [expected], "parsing {:?}", source);
+        }
+
+        // Only the Verus crates are reported
+        let lock = "[[package]]\nname = \"serde\"\nversion = \"1.0.0\"\n\n[[package]]\nname = \"builtin\"\nversion = \"0.1.0\"\n";
+        let names: Vec<_> = parse_locked_verus_crates(lock).unwrap().into_iter().map(|locked| locked.name).collect();
+        assert_eq!(names, ["builtin"]);
+    }
+
+    #[test]
+    fn pinned_dependency_tables() {
+        let (url, rev) = ("https://github.com/verus-lang/verus.git", "0123abcd");
+        let pinned = format!("{{ git = \"{}\", rev = \"{}\" }}", url, rev);
+        // (dependency, expected dependency, whether it is pinned)
+        let cases = [
+            (format!("vstd = {{ git = \"{}\", branch = \"main\" }}", url), format!("vstd = {}", pinned), true),
+            (format!("vstd = {{ git = \"{}\", tag = \"release\" }}", url), format!("vstd = {}", pinned), true),
+            (format!("vstd = {{ git = \"{}\", rev = \"4567\" }}", url), format!("vstd = {}", pinned), true),
+            ("builtin = { git = \"https://example.com/verus.git\" }".to_string(), format!("builtin = {}", pinned), true),
+            (format!("vstd = {}", pinned), format!("vstd = {}", pinned), false),
+            // Renamed dependencies are pinned by their package
+            (
+                format!("my_vstd = {{ package = \"vstd\", git = \"{}\" }}", url),
+                format!("my_vstd = {{ package = \"vstd\", git = \"{}\", rev = \"{}\" }}", url, rev),
+                true,
+            ),
+            ("vstd = { workspace = true }".to_string(), "vstd = { workspace = true }".to_string(), false),
+            ("vstd = \"0.1\"".to_string(), "vstd = \"0.1\"".to_string(), false),
+            ("vstd = { path = \"../vstd\" }".to_string(), "vstd = { path = \"../vstd\" }".to_string(), false),
+            ("serde = { git = \"https://example.com/serde.git\" }".to_string(), "serde = { git = \"https://example.com/serde.git\" }".to_string(), false),
+        ];
+
+        for (dependency, expected, is_pinned) in cases {
+            let mut document = format!("[dependencies]\n{}\n", dependency).parse::<toml_edit::DocumentMut>().unwrap();
+            let table = document["dependencies"].as_table_like_mut().unwrap();
+            let names = pin_dependency_table(table, url, rev);
+
+            let actual = toml::from_str::<toml::Table>(&document.to_string()).unwrap();
+            let expected = toml::from_str::<toml::Table>(&format!("[dependencies]\n{}\n", expected)).unwrap();
+            assert_eq!(actual, expected, "pinning {:?}", dependency);
+            assert_eq!(!names.is_empty(), is_pinned, "pinned names of {:?}", dependency);
+        }
+    }
+}
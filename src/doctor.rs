@@ -0,0 +1,329 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+use colored::*;
+
+use crate::config;
+use crate::deps;
+use crate::toolchain;
+use crate::version::EMBEDDED_PROVENANCE;
+use crate::wrapper;
+
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+/// Result of a single check of `vargo doctor`
+struct Check {
+    name: &'static str,
+    status: Status,
+    details: String,
+    /// How to fix a warning or failure
+    hint: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, details: impl Into<String>) -> Check {
+        Check { name, status, details: details.into(), hint: None }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Check {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+/// Runs `<program> <args>` and returns the first line of its stdout if it succeeds
+fn first_output_line(program: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).lines().next()?.trim().to_string())
+}
+
+/// The selected Verus runs
+fn check_verus(resolved: &anyhow::Result<(Option<String>, String)>) -> Check {
+    let (toolchain_name, verus_path) = match resolved {
+        Ok(resolved) => resolved,
+        Err(err) => return Check::new("verus", Status::Fail, format!("{:#}", err))
+            .hint("see `vargo toolchain list`, or set VERUS_PATH"),
+    };
+
+    let source = match toolchain_name {
+        Some(name) => format!("toolchain `{}`", name),
+        None => "VERUS_PATH or PATH".to_string(),
+    };
+
+    match first_output_line(Path::new(verus_path), &["--version"]) {
+        Some(version) => Check::new("verus", Status::Pass, format!("{} ({}, {})", version, verus_path, source)),
+        None => Check::new("verus", Status::Fail, format!("{} ({}) does not run", verus_path, source))
+            .hint("reinstall the toolchain, or remove ~/.vargo/embedded to extract the embedded one again"),
+    }
+}
+
+/// Z3 runs and has the version the embedded Verus was built with
+fn check_z3(toolchain_name: Option<&str>, toolchain_dir: &Path) -> Check {
+    // Verus uses VERUS_Z3_PATH if set, and otherwise the Z3 next to it
+    let (z3, source) = match env::var("VERUS_Z3_PATH") {
+        Ok(path) => (PathBuf::from(path), " (from VERUS_Z3_PATH)"),
+        Err(_) => (toolchain_dir.join("z3"), ""),
+    };
+
+    let Some(output) = first_output_line(&z3, &["--version"]) else {
+        return Check::new("z3", Status::Fail, format!("{}{} does not run", z3.display(), source))
+            .hint("unset VERUS_Z3_PATH or point it to a working Z3, or reinstall the toolchain");
+    };
+
+    // e.g. `Z3 version 4.12.5 - 64 bit`
+    let version = output.split_whitespace()
+        .skip_while(|word| *word != "version")
+        .nth(1)
+        .unwrap_or_default();
+
+    let expected = EMBEDDED_PROVENANCE.z3_version;
+    if toolchain_name == Some(toolchain::EMBEDDED_TOOLCHAIN) && !expected.is_empty() && version != expected {
+        return Check::new("z3", Status::Fail, format!(
+            "{}{} has version {}, but Verus was built with {}", z3.display(), source, version, expected,
+        )).hint(format!("unset VERUS_Z3_PATH or point it to Z3 {}", expected));
+    }
+
+    Check::new("z3", Status::Pass, format!("{} ({}{})", version, z3.display(), source))
+}
+
+/// Cargo runs with the Rust toolchain that Verus was built with
+fn check_rust_toolchain(toolchain_dir: &Path) -> Check {
+    let required = match toolchain::required_rust_toolchain(toolchain_dir) {
+        Ok(Some(required)) => required,
+        Ok(None) => return Check::new("rust toolchain", Status::Skip,
+            "the Verus toolchain does not record its Rust toolchain"),
+        Err(err) => return Check::new("rust toolchain", Status::Fail, format!("{:#}", err)),
+    };
+
+    if env::var("VARGO_RUST_TOOLCHAIN").as_deref() == Ok("off") {
+        return Check::new("rust toolchain", Status::Warn, format!(
+            "Verus requires `{}`, but VARGO_RUST_TOOLCHAIN=off uses the current toolchain", required,
+        )).hint("unset VARGO_RUST_TOOLCHAIN unless the current toolchain is the same");
+    }
+
+    match toolchain::rust_toolchain_arg(toolchain_dir) {
+        Ok(Some(arg)) => {
+            let rustc = first_output_line(Path::new("rustc"), &[&arg, "--version"]).unwrap_or_default();
+            Check::new("rust toolchain", Status::Pass, format!("cargo {} ({})", arg, rustc))
+        }
+        // Without rustup, the current rustc has to be the right one
+        Ok(None) => {
+            let rustc = first_output_line(Path::new("rustc"), &["--version"]).unwrap_or_default();
+            let version = rustc.split_whitespace().nth(1).unwrap_or_default();
+            if required.starts_with(version) || required.split('-').next() == Some(version) {
+                Check::new("rust toolchain", Status::Pass, rustc)
+            } else {
+                Check::new("rust toolchain", Status::Fail, format!(
+                    "Verus requires `{}`, but rustup is not available and the current rustc is {}", required, rustc,
+                )).hint(format!("install rustup, or put rustc `{}` on PATH", required))
+            }
+        }
+        Err(_) => Check::new("rust toolchain", Status::Fail, format!("Verus requires `{}`, which is not installed", required))
+            .hint(format!("run `rustup toolchain install {}`", required)),
+    }
+}
+
+/// The vstd revisions locked in Cargo.lock match the selected Verus
+fn check_vstd(toolchain_name: Option<&str>, toolchain_dir: &Path, manifest: Option<&Path>) -> Check {
+    let Some(manifest) = manifest else {
+        return Check::new("vstd revision", Status::Skip, "not in a cargo project");
+    };
+    let Some(commit) = toolchain::selected_toolchain_commit(toolchain_name, toolchain_dir) else {
+        return Check::new("vstd revision", Status::Skip, "no Verus commit is recorded for the selected toolchain");
+    };
+
+    let lock_path = manifest.with_file_name("Cargo.lock");
+    if !lock_path.exists() {
+        return Check::new("vstd revision", Status::Skip, "no Cargo.lock yet");
+    }
+
+    let version = toolchain::toolchain_version(toolchain_dir);
+    let local_vstd = deps::local_vstd_mode(manifest, &[]).is_ok_and(|(enabled, _)| enabled);
+    match deps::vstd_mismatches(&lock_path, &commit, version.as_deref(), local_vstd) {
+        Ok(mismatches) if mismatches.is_empty() =>
+            Check::new("vstd revision", Status::Pass, format!("locked to {}", commit)),
+        Ok(mismatches) => Check::new("vstd revision", Status::Fail, format!(
+            "{} (the selected Verus is at {})", mismatches.join("; "), commit,
+        )).hint("run `vargo fix-deps`"),
+        Err(err) => Check::new("vstd revision", Status::Fail, format!("{:#}", err)),
+    }
+}
+
+/// Wrappers configured before vargo (e.g. sccache) are chained by vargo
+fn check_rustc_wrapper() -> Check {
+    let wrappers: Vec<String> = [wrapper::existing_wrapper(false), wrapper::existing_wrapper(true)]
+        .into_iter()
+        .flatten()
+        .map(|inner| format!("{} (from {})", inner.program, inner.source))
+        .collect();
+
+    if wrappers.is_empty() {
+        return Check::new("rustc wrapper", Status::Pass, "no other rustc wrapper configured");
+    }
+    Check::new("rustc wrapper", Status::Pass, format!("chained with {}", wrappers.join("; ")))
+}
+
+/// The target directory of the workspace is writable
+fn check_target_dir(manifest: Option<&Path>) -> Check {
+    if manifest.is_none() {
+        return Check::new("target dir", Status::Skip, "not in a cargo project");
+    }
+
+    let target_dir = match deps::workspace_metadata(None) {
+        Ok(metadata) => match metadata["target_directory"].as_str() {
+            Some(dir) => PathBuf::from(dir),
+            None => return Check::new("target dir", Status::Fail, "missing target_directory in cargo metadata"),
+        },
+        Err(err) => return Check::new("target dir", Status::Fail, format!("{:#}", err)),
+    };
+
+    let probe = target_dir.join(".vargo-doctor");
+    let result = fs::create_dir_all(&target_dir)
+        .and_then(|_| fs::write(&probe, ""))
+        .and_then(|_| fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => Check::new("target dir", Status::Pass, format!("{} is writable", target_dir.display())),
+        Err(err) => Check::new("target dir", Status::Fail, format!("{} is not writable: {}", target_dir.display(), err))
+            .hint("fix the permissions, or set CARGO_TARGET_DIR to a writable directory"),
+    }
+}
+
+/// Checks the keys of the Verus settings of a manifest, returning the unknown ones
+/// and whether the deprecated `[verus]` table is used
+fn check_verus_settings(manifest: &Path) -> anyhow::Result<(Vec<String>, bool)> {
+    let settings = config::read_settings(manifest)?;
+
+    let mut unknown = Vec::new();
+    for (key, value) in settings.tables().into_iter().flatten() {
+        match key.as_str() {
+            "toolchain" => {
+                toolchain::ToolchainPin::parse(value, manifest)?;
+            }
+            "support_crates" => {
+                let crates = value.as_array()
+                    .with_context(|| format!("Expected an array for `support_crates` in {}", manifest.display()))?;
+                if !crates.iter().all(toml::Value::is_str) {
+                    bail!("Expected crate names (strings) in `support_crates` in {}", manifest.display());
+                }
+            }
+            "verify" => {
+                value.as_bool()
+                    .with_context(|| format!("Expected true or false for `verify` in {}", manifest.display()))?;
+            }
+            "extra_flags" | "vstd_check" | "local_vstd" => {}
+            _ => unknown.push(format!("{} in {}", key, manifest.display())),
+        }
+    }
+
+    // Reuse the parsers of the other keys
+    settings.extra_flags(manifest)?;
+    deps::vstd_check_mode(manifest)?;
+    deps::local_vstd_mode(manifest, &[])?;
+
+    Ok((unknown, settings.deprecated))
+}
+
+/// The Verus settings of the workspace (and the pinned toolchain) parse
+fn check_verus_tables(manifest: &anyhow::Result<PathBuf>) -> Check {
+    if let Err(err) = manifest {
+        // Distinguish broken manifests from not being in a cargo project at all
+        let in_project = env::current_dir().is_ok_and(|cwd| cwd.ancestors().any(|dir| dir.join("Cargo.toml").is_file()));
+        return if in_project {
+            Check::new("verus settings", Status::Fail, format!("{:#}", err)).hint("fix Cargo.toml")
+        } else {
+            Check::new("verus settings", Status::Skip, "not in a cargo project")
+        };
+    }
+
+    let result = (|| -> anyhow::Result<(usize, Vec<String>, Vec<String>)> {
+        let (members, root) = deps::workspace_members(None)?;
+        let mut manifests = vec![root];
+        manifests.extend(members.into_iter().map(|member| member.manifest_path));
+        manifests.dedup();
+
+        let mut unknown = Vec::new();
+        let mut deprecated = Vec::new();
+        for manifest in &manifests {
+            let (manifest_unknown, manifest_deprecated) = check_verus_settings(manifest)?;
+            unknown.extend(manifest_unknown);
+            if manifest_deprecated {
+                deprecated.push(manifest.display().to_string());
+            }
+        }
+        toolchain::pinned_toolchain()?;
+
+        Ok((manifests.len(), unknown, deprecated))
+    })();
+
+    match result {
+        Ok((_, unknown, _)) if !unknown.is_empty() =>
+            Check::new("verus settings", Status::Warn, format!("unknown keys: {}", unknown.join("; ")))
+                .hint("check for typos (known keys: extra_flags, toolchain, vstd_check, local_vstd, support_crates, verify)"),
+        Ok((_, _, deprecated)) if !deprecated.is_empty() =>
+            Check::new("verus settings", Status::Warn, format!("deprecated `[verus]` table in {}", deprecated.join(", ")))
+                .hint("move it to `[package.metadata.verus]` (or `[workspace.metadata.verus]` in a virtual workspace root)"),
+        Ok((count, _, _)) => Check::new("verus settings", Status::Pass, format!("{} manifest(s) checked", count)),
+        Err(err) => Check::new("verus settings", Status::Fail, format!("{:#}", err))
+            .hint("fix the Verus settings (see the README)"),
+    }
+}
+
+/// Entry point of `vargo doctor`
+///
+/// Checks everything vargo relies on and prints a table of the results,
+/// returning a non-zero exit code if any check fails.
+pub fn doctor_command(args: &[String], toolchain_override: Option<String>) -> anyhow::Result<i32> {
+    if !args.is_empty() {
+        bail!("Usage: vargo doctor");
+    }
+
+    let resolved = crate::resolve_verus(toolchain_override);
+    let manifest_result = deps::workspace_manifest(None);
+    let manifest = manifest_result.as_ref().ok().cloned();
+
+    let mut checks = vec![check_verus(&resolved)];
+    if let Ok((toolchain_name, verus_path)) = &resolved {
+        let toolchain_dir = Path::new(verus_path).parent().unwrap_or(Path::new("."));
+        checks.push(check_z3(toolchain_name.as_deref(), toolchain_dir));
+        checks.push(check_rust_toolchain(toolchain_dir));
+        checks.push(check_vstd(toolchain_name.as_deref(), toolchain_dir, manifest.as_deref()));
+    }
+    checks.push(check_rustc_wrapper());
+    checks.push(check_target_dir(manifest.as_deref()));
+    checks.push(check_verus_tables(&manifest_result));
+
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or_default();
+    for check in &checks {
+        // Pad before coloring, since escape codes would count towards the width
+        let status = match check.status {
+            Status::Pass => "ok  ".bold().green(),
+            Status::Warn => "warn".bold().yellow(),
+            Status::Fail => "FAIL".bold().red(),
+            Status::Skip => "skip".dimmed(),
+        };
+        println!("{:<width$}  {}  {}", check.name, status, check.details, width = width);
+        if let Some(hint) = &check.hint {
+            println!("{:<width$}        hint: {}", "", hint, width = width);
+        }
+    }
+
+    let failures = checks.iter().filter(|check| matches!(check.status, Status::Fail)).count();
+    if failures > 0 {
+        println!("\n{} check(s) failed", failures);
+        return Ok(1);
+    }
+
+    Ok(0)
+}
@@ -1,60 +1,31 @@
 use std::env;
 use std::fs;
-use std::io::{self, Write, BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::time::Instant;
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 
 use regex::Regex;
 use colored::*;
 
-use include_dir::{include_dir, Dir};
-
-static VERUS_TARGET: Dir = include_dir!("$CARGO_MANIFEST_DIR/target/verus");
-
-// TODO: windows
-use std::os::unix::fs::PermissionsExt;
-
-/// Set file mode to executable
-fn set_executable(file: &Path) -> io::Result<()> {
-    let metadata = fs::metadata(file)?;
-    let mut permissions = metadata.permissions();
-    permissions.set_mode(permissions.mode() | 0o111);
-    fs::set_permissions(file, permissions)
-}
-
-/// Recursively dumps the contents of a `Dir` to a specified path
-fn extract_dir(dir: &Dir, dest: &Path) -> io::Result<()> {
-    for file in dir.files() {
-        let relative_path = file.path();
-        let dest_path = dest.join(relative_path);
-
-        // Create all parent directories
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::write(&dest_path, file.contents())?;
-    }
-
-    for subdir in dir.dirs() {
-        let sub_dest = dest.join(subdir.path());
-        fs::create_dir_all(&sub_dest)?;
-        extract_dir(subdir, &dest)?;
-    }
-
-    Ok(())
-}
-
-/// Parse next_arg as <extern_name>=<prefix>-<extern_hash>.<ext>
-/// and return (extern_name, extern_hash)
-fn parse_extern_argument(s: &str) -> Option<(&str, &str)> {
-    let (name, rest) = s.split_once('=')?;
-    let (rest, _) = rest.rsplit_once('.')?;
-    let (_, hash) = rest.rsplit_once('-')?;
-    Some((name, hash))
-}
+use artifacts::VerifyArtifacts;
+use common::VERUS_CRATES;
+use rustc_args::{Arg, Extern};
+
+mod artifacts;
+mod common;
+mod config;
+mod deps;
+mod doctor;
+mod new;
+mod rustc_args;
+mod toolchain;
+mod version;
+mod wrapper;
+#[cfg(test)]
+mod test_util;
 
 /// Check if a string is a rustc artifact message
 fn is_artifact_message(s: &str) -> bool {
@@ -66,18 +37,31 @@ fn is_artifact_message(s: &str) -> bool {
     false
 }
 
-/// Tries to get additional Verus flags from the crate's Cargo.toml
-fn get_verus_flags(path: &str) -> Option<String> {
-    let content = fs::read_to_string(path).ok()?;
-    let parsed = toml::from_str::<toml::Value>(&content).ok()?;
+/// Names that newer Verus releases give to `builtin` and `builtin_macros`
+const RENAMED_VERUS_CRATES: [&str; 2] = ["verus_builtin", "verus_builtin_macros"];
+
+/// Passes `--verus-flags` from the command line to vargo running as `RUSTC_WRAPPER`
+const CLI_VERUS_FLAGS_VAR: &str = "VARGO_CLI_VERUS_FLAGS";
 
-    if let Some(section) = parsed.get("verus") {
-        if let Some(flags) = section.get("extra_flags") {
-            return Some(flags.as_str()?.to_string());
+/// The Verus support crates, including the ones added by `support_crates` in the crate's settings
+fn get_support_crates(settings: &config::Settings) -> Vec<String> {
+    let mut crates: Vec<String> = VERUS_CRATES.iter().chain(&RENAMED_VERUS_CRATES).map(|name| name.to_string()).collect();
+
+    let extra = settings.get("support_crates").and_then(|value| value.as_array());
+    crates.extend(extra.into_iter().flatten().filter_map(|name| name.as_str().map(str::to_string)));
+    crates
+}
+
+/// Additional Verus flags, from the lowest to the highest precedence: `extra_flags` of the workspace,
+/// then of the crate, then `VERUS_FLAGS`, then `--verus-flags` on the command line
+fn get_verus_flags(settings: &config::Settings, manifest: &Path) -> anyhow::Result<Vec<String>> {
+    let mut sources = settings.extra_flags(manifest)?;
+    for var in ["VERUS_FLAGS", CLI_VERUS_FLAGS_VAR] {
+        if let Ok(flags) = env::var(var) {
+            sources.push(shell_words::split(&flags).with_context(|| format!("Invalid {}", var))?);
         }
     }
-
-    None
+    Ok(config::merge_flags(&sources))
 }
 
 enum Level {
@@ -96,132 +80,182 @@ fn cargo_message(level: Level, banner: &str, msg: &str) {
     )
 }
 
+/// Fake a Cargo warning
+fn cargo_warning(msg: &str) {
+    eprintln!("{} {}", "warning:".bold().yellow(), msg);
+}
+
+/// Whether dependencies outside the workspace are verified too (`--verify-deps` or `VARGO_VERIFY_DEPS`)
+fn verify_deps_enabled() -> bool {
+    env::var("VARGO_VERIFY_DEPS").is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false" | "off"))
+}
+
 /// Based on the arguments to rustc, call Verus if the given crate should be verified
-fn check_verification(args: &Vec<String>, verus_path: &str) -> anyhow::Result<()> {
+///
+/// If `trusted` is set, the crate is only exported for its dependents, without checking its proofs.
+fn check_verification(args: &[String], verus_path: &str, trusted: bool) -> anyhow::Result<()> {
+    // The translated rustc arguments, and Verus's own `--import` options
     let mut verus_args = Vec::new();
+    let mut import_args = Vec::new();
     let mut use_verus = false;
 
-    let mut deps_dir = None;
-    let mut hash = None;
-
-    let mut iter = args.iter().peekable();
-
-    // Filter arguments for Verus
-    while let Some(arg) = iter.next() {
-
-        // Process some special cases
-        if arg == "--extern" {
-            if let Some(next_arg) = iter.peek() {
-                if next_arg.starts_with("vstd=") {
-                    // Call Verus iff --extern vstd=... is part of the arguments
+    let rustc_args = rustc_args::parse(args)?;
+    let deps_dir = rustc_args.iter().rev().find_map(|arg| match arg {
+        Arg::OutDir(dir) => Some(dir.clone()),
+        _ => None,
+    });
+    let search_dirs = artifacts::crate_search_dirs(&rustc_args);
+    let toolchain_dir = Path::new(verus_path).parent().unwrap_or(Path::new("."));
+    // Not set when cargo runs the wrapper to query rustc (e.g. `rustc -vV`)
+    let crate_path = env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let settings = if crate_path.is_empty() {
+        config::Settings::default()
+    } else {
+        config::crate_settings(Path::new(&crate_path))?
+    };
+    let support_crates = get_support_crates(&settings);
+    let mut crate_name = None;
+    let mut extra_filename = String::new();
+    let mut missing_exports = Vec::new();
+
+    // Translate the arguments for Verus
+    for arg in rustc_args {
+        match &arg {
+            Arg::Extern(ext) => {
+                // Identify the crate by its file rather than the extern name (which may be renamed)
+                let crate_name = artifacts::extern_crate_name(ext);
+
+                // Call Verus iff vstd is one of the externs
+                if crate_name == "vstd" {
                     use_verus = true;
                 }
 
                 // Remove --extern vstd/builtin/builtin_macros=...
                 // when calling Verus (otherwise all the Verus code
                 // would be stripped)
-                if next_arg.starts_with("vstd=")
-                    || next_arg.starts_with("builtin=")
-                    || next_arg.starts_with("builtin_macros=")
-                {
-                    iter.next();
+                if support_crates.contains(&crate_name) {
+                    // Verus only passes its own copies under their crate names,
+                    // so point renamed dependencies to the same files
+                    if ext.name != crate_name {
+                        if let Some(path) = artifacts::toolchain_crate(toolchain_dir, &crate_name) {
+                            let path = path.to_string_lossy().to_string();
+                            verus_args.extend(Arg::Extern(Extern { path: Some(path), ..ext.clone() }).to_args());
+                        }
+                    }
                     continue;
                 }
 
                 // If verusdata exists, we assume that the extern crate is a Verus project
                 // so we need to import .verusdata
-                if let Some((name, hash)) = parse_extern_argument(next_arg) {
-                    if let Some(deps_dir) = &deps_dir {
-                        let verusdata_path = format!("{}/verify/{}-{}.verusdata", deps_dir, name, hash);
-                        let verus_rmeta_path = format!("{}/verify/lib{}-{}.rmeta", deps_dir, name, hash);
-
-                        if Path::new(&verusdata_path).exists() && Path::new(&verus_rmeta_path).exists() {
-                            verus_args.push("--import".to_string());
-                            verus_args.push(format!("{}={}", name, verusdata_path));
-                            verus_args.push("--extern".to_string());
-                            verus_args.push(format!("{}={}", name, verus_rmeta_path));
-                            iter.next();
-                            continue;
-                        }
+                if let Some(exports) = artifacts::resolve_extern(ext, &search_dirs) {
+                    import_args.push("--import".to_string());
+                    import_args.push(format!("{}={}", ext.name, exports.verusdata.display()));
+                    let rmeta = exports.rmeta.to_string_lossy().to_string();
+                    verus_args.extend(Arg::Extern(Extern { path: Some(rmeta), ..ext.clone() }).to_args());
+                    continue;
+                }
+
+                // vargo marks the crates it compiles without exports, so this one was compiled without vargo
+                if let Some(path) = &ext.path {
+                    if !artifacts::is_unverified(Path::new(path)) {
+                        missing_exports.push((ext.name.clone(), path.clone()));
                     }
                 }
             }
-        } else if arg == "-C" {
-            // Try to find the hash of the current crate being built
-            if let Some(next_arg) = iter.peek() {
-                if next_arg.starts_with("metadata=") {
-                    hash = Some(next_arg["metadata=".len()..].to_string());
-                }
+            Arg::Codegen { key, value: Some(value) } if key == "extra-filename" => {
+                // Suffix of the output files of the current crate (e.g. `-<hash>`)
+                extra_filename = value.clone();
             }
-        } else if arg.starts_with("--edition=") {
+            Arg::CrateName(name) => crate_name = Some(name.clone()),
             // Ignore --edition=* arguments, since Verus already provides it to rustc
-            continue;
-        } else if arg == "--out-dir" {
-            // Rewrite --out-dir <dir> to --out-dir <dir>/verify
-            // and also record <dir>
-            if let Some(next_arg) = iter.peek() {
-                deps_dir = Some(next_arg.to_string());
-                verus_args.push("--out-dir".to_string());
-                verus_args.push(format!("{}/verify", next_arg));
-                iter.next();
+            Arg::Edition(_) => continue,
+            Arg::OutDir(dir) => {
+                // Rewrite --out-dir <dir> to --out-dir <dir>/verify
+                verus_args.extend(Arg::OutDir(format!("{}/verify", dir)).to_args());
                 continue;
             }
-        } else if arg.starts_with("--emit=") {
             // Overwrite --emit flags
-            continue;
+            Arg::Emit(_) => continue,
+            _ => {}
         }
 
         // Otherwise just use the same argument
-        verus_args.push(arg.clone());
+        verus_args.extend(arg.to_args());
     }
 
-    // If no vstd dependency, then no need to call verus
-    if !use_verus {
+    // If no deps_dir found, skip verus
+    let Some(deps_dir) = deps_dir else {
         return Ok(());
-    }
+    };
 
-    // If no deps_dir or hash found, also skip verus
-    let (Some(deps_dir), Some(hash)) = (deps_dir, hash) else {
-        return Ok(());
+    let crate_name = match crate_name {
+        Some(name) => name,
+        None => env::var("CARGO_CRATE_NAME")?,
     };
 
+    // Verus names the rmeta like rustc (with `-C extra-filename`), so that dependents can find both
+    let exports = VerifyArtifacts::new(Path::new(&deps_dir), &format!("{}{}", crate_name, extra_filename));
+
+    // If no vstd dependency, then no need to call verus, unless `verify` says otherwise
+    // (dependents then use the crate as plain Rust)
+    let verify = settings.get("verify").and_then(|value| value.as_bool());
+    let verified = verify.unwrap_or(use_verus);
+    exports.set_unverified(!verified)?;
+    if !verified {
+        return Ok(());
+    }
+
+    for (name, path) in missing_exports {
+        cargo_warning(&format!(
+            "dependency `{}` has no Verus export (.verusdata) next to {} or in the -L dependency directories \
+            and was not compiled by vargo, so if it uses vstd, its specifications are not imported; \
+            rebuild it with vargo (e.g. after `cargo clean -p <package>`)",
+            name, path,
+        ));
+    }
+
     // Prepare and call verus command
-    let crate_name = env::var("CARGO_CRATE_NAME")?;
     let crate_version = env::var("CARGO_PKG_VERSION")?;
-    let crate_path = env::var("CARGO_MANIFEST_DIR")?;
 
-    cargo_message(Level::Note, "Verifying", &format!("{} v{} ({})", crate_name, crate_version, crate_path));
+    let manifest = Path::new(&crate_path).join("Cargo.toml");
+    if settings.deprecated {
+        config::warn_deprecated(&manifest);
+    }
+
+    let banner = if trusted { "Importing" } else { "Verifying" };
+    cargo_message(Level::Note, banner, &format!("{} v{} ({})", crate_name, crate_version, crate_path));
 
     // Create deps_dir/verify if it does not exist
     let verify_deps_dir = format!("{}/verify", deps_dir);
     fs::create_dir_all(&verify_deps_dir)?;
 
-    let verusdata_path = format!(
-        "{}/{}-{}.verusdata",
-        verify_deps_dir, crate_name, hash
-    );
-
     let mut verus_cmd = Command::new(verus_path);
+    verus_cmd.env_remove("CARGO_MAKEFLAGS");
+
+    // Cargo passes long command lines in an argument file, so do the same for the rustc arguments
+    // passed on to Verus (Verus's own options stay on the command line)
+    if rustc_args::has_argfile(args) {
+        rustc_args::write_argfile(&exports.args, &verus_args)
+            .with_context(|| format!("Failed to write {}", exports.args.display()))?;
+        verus_cmd.arg(format!("@{}", exports.args.display()));
+    } else {
+        verus_cmd.args(&verus_args);
+    }
+
     verus_cmd
-        .env_remove("CARGO_MAKEFLAGS")
-        .args(&verus_args)
+        .args(&import_args)
         .arg("-L").arg(format!("dependency={}", verify_deps_dir))
         .arg("--emit=dep-info,metadata") // Don't do any compiling/linking
         .arg("--no-report-long-running")
         .arg("--compile")
-        .arg("--export").arg(&verusdata_path)
+        .arg("--export").arg(&exports.verusdata)
+        // Trusted crates are only exported (.verusdata and the verify rmeta) for their dependents
+        .args(trusted.then_some("--no-verify"))
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    // Add optional flags from VERUS_FLAGS
-    if let Ok(flags) = env::var("VERUS_FLAGS") {
-        verus_cmd.args(shell_words::split(&flags)?);
-    }
-
-    // Add optional flags from Cargo.toml
-    if let Some(flags) = get_verus_flags(&format!("{}/Cargo.toml", crate_path)) {
-        verus_cmd.args(shell_words::split(&flags)?);
-    }
+    // Add optional flags from Cargo.toml, VERUS_FLAGS and the command line
+    verus_cmd.args(get_verus_flags(&settings, &manifest)?);
 
     // println!("running verus: {:?}", verus_cmd);
     let start = Instant::now();
@@ -233,10 +267,11 @@ fn check_verification(args: &Vec<String>, verus_path: &str) -> anyhow::Result<()
 
     // Filter JSON messages in stderr (ignore artifact messages)
     for line in reader.lines() {
-        if let Ok(line) = line {
-            if !is_artifact_message(&line) {
-                eprintln!("{}", &line);
-            }
+        let Ok(line) = line else {
+            continue;
+        };
+        if !is_artifact_message(&line) {
+            eprintln!("{}", &line);
         }
     }
 
@@ -247,19 +282,24 @@ fn check_verification(args: &Vec<String>, verus_path: &str) -> anyhow::Result<()
     let result_re = Regex::new(r"^verification results:: (\d+) verified, (\d+) errors$")?;
 
     for line in reader.lines() {
-        if let Ok(line) = line {
-            if let Some(cap) = result_re.captures(&line) {
-                if let (Some(num_suc), Some(num_fail)) = (cap.get(1), cap.get(2)) {
-                    let elapsed = start.elapsed().as_secs_f64();
-
-                    let num_suc: usize = num_suc.as_str().parse()?;
-                    let num_fail: usize = num_fail.as_str().parse()?;
-
-                    if num_fail == 0 {
-                        cargo_message(Level::Note, "Verus", &format!("{}: {} verified in {:.2}s", crate_name, num_suc, elapsed));
-                    } else {
-                        cargo_message(Level::Error, "Verus", &format!("{}: {} verified, {} failed, in {:.2}s", crate_name, num_suc, num_fail, elapsed));
-                    }
+        let Ok(line) = line else {
+            continue;
+        };
+        // Nothing was verified in a trusted crate (but its output still needs to be drained)
+        if trusted {
+            continue;
+        }
+        if let Some(cap) = result_re.captures(&line) {
+            if let (Some(num_suc), Some(num_fail)) = (cap.get(1), cap.get(2)) {
+                let elapsed = start.elapsed().as_secs_f64();
+
+                let num_suc: usize = num_suc.as_str().parse()?;
+                let num_fail: usize = num_fail.as_str().parse()?;
+
+                if num_fail == 0 {
+                    cargo_message(Level::Note, "Verus", &format!("{}: {} verified in {:.2}s", crate_name, num_suc, elapsed));
+                } else {
+                    cargo_message(Level::Error, "Verus", &format!("{}: {} verified, {} failed, in {:.2}s", crate_name, num_suc, num_fail, elapsed));
                 }
             }
         }
@@ -270,25 +310,124 @@ fn check_verification(args: &Vec<String>, verus_path: &str) -> anyhow::Result<()
         Err(anyhow!("Verus failed with non-zero exit code"))?;
     }
 
+    if trusted {
+        let elapsed = start.elapsed().as_secs_f64();
+        cargo_message(Level::Note, "Verus", &format!("{}: trusted, not verified, in {:.2}s", crate_name, elapsed));
+    }
+
     Ok(())
 }
 
+/// Cargo commands that compile (and hence verify) crates
+const BUILD_COMMANDS: [&str; 12] = [
+    "build", "b", "check", "c", "test", "t", "bench", "run", "r", "doc", "d", "clippy",
+];
+
+/// Cargo's global options that take a value (e.g. `vargo --config <key=value> build`)
+const GLOBAL_VALUE_OPTIONS: [&str; 4] = ["--color", "--config", "-Z", "-C"];
+
+/// Finds the cargo subcommand in `args`, after any global options (e.g. `vargo --offline -v build`)
+fn cargo_subcommand(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if GLOBAL_VALUE_OPTIONS.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Whether cargo arguments (before any `--`) set the target directory
+fn has_target_dir_arg(args: &[String]) -> bool {
+    args.iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--target-dir" || arg.starts_with("--target-dir="))
+}
+
+/// Resolves which Verus to use: `+<toolchain>`, then VERUS_PATH, then the toolchain
+/// pinned by the project, then the default toolchain (the built-in one unless configured otherwise),
+/// and finally `verus` on PATH
+///
+/// Returns the name of the toolchain (if any) and the path to the `verus` executable.
+fn resolve_verus(toolchain_override: Option<String>) -> anyhow::Result<(Option<String>, String)> {
+    let toolchain_name = if toolchain_override.is_some() {
+        toolchain_override
+    } else if env::var("VERUS_PATH").is_ok() {
+        None
+    } else if let Some((pin, pin_path)) = toolchain::pinned_toolchain()? {
+        Some(pin.resolve().with_context(|| format!("{} requires Verus {}", pin_path.display(), pin))?)
+    } else {
+        toolchain::default_toolchain()?
+    };
+
+    let verus_path = match &toolchain_name {
+        Some(name) => toolchain::toolchain_dir(name)?.join("verus"),
+        None => match env::var("VERUS_PATH") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => toolchain::verus_on_path().context(
+                "No Verus found: this vargo has no embedded Verus toolchain, so either set VERUS_PATH, \
+                install a toolchain with `vargo toolchain install` and make it the default \
+                with `vargo toolchain default`, or put `verus` on PATH"
+            )?,
+        },
+    };
+    let verus_path = verus_path.to_str()
+        .context("Invalid character in verus path")?.to_string();
+
+    Ok((toolchain_name, verus_path))
+}
+
 fn vargo() -> anyhow::Result<i32> {
     let mut args = env::args().skip(1);
 
     // If `VARGO_AS_RUSTC` is set, we are using `vargo` in `RUSTC_WRAPPER`
     if env::var("VARGO_AS_RUSTC").is_ok() {
         if let Ok(verus_path) = env::var("VERUS_PATH") {
-            let rustc_path = args.next()
+            let mut rustc_path = args.next()
                 .context("When used as RUSTC_WRAPPER, vargo expects at least one argument for the rustc path")?;
 
+            // vargo is also the RUSTC_WORKSPACE_WRAPPER, so cargo passes the vargo executable
+            // before the rustc path iff the crate is a workspace member
+            let is_member = wrapper::is_vargo(&rustc_path);
+            if is_member {
+                rustc_path = args.next()
+                    .context("Expected the rustc path after RUSTC_WORKSPACE_WRAPPER")?;
+            }
+
+            // Wrappers configured before vargo are run around rustc,
+            // with the workspace wrapper only for workspace members
+            let mut wrappers = Vec::new();
+            if let Ok(inner) = env::var(wrapper::INNER_WRAPPER_VAR) {
+                wrappers.push(inner);
+            }
+            if is_member {
+                if let Ok(inner) = env::var(wrapper::INNER_WORKSPACE_WRAPPER_VAR) {
+                    wrappers.push(inner);
+                }
+            }
+
             let rustc_args: Vec<String> = args.collect();
 
-            check_verification(&rustc_args, &verus_path)
+            // Dependencies are trusted (not verified) unless asked otherwise
+            let trusted = !is_member && !verify_deps_enabled();
+
+            // Verify outside of the wrappers, so that verification is not skipped
+            // when a compiler cache (e.g. sccache) has the compiled crate
+            check_verification(&rustc_args, &verus_path, trusted)
                 .context("Failed to call Verus")?;
 
-            // Always call rustc at the end
-            return Ok(Command::new(&rustc_path)
+            // Always call rustc at the end (through the wrappers)
+            let mut command = match wrappers.split_first() {
+                Some((first, rest)) => {
+                    let mut command = Command::new(first);
+                    command.args(rest).arg(&rustc_path);
+                    command
+                }
+                None => Command::new(&rustc_path),
+            };
+            return Ok(command
                 .args(&rustc_args)
                 .status()
                 .context("Failed to run rustc")?
@@ -297,73 +436,142 @@ fn vargo() -> anyhow::Result<i32> {
         }
     }
 
-    // Extract the built-in version of Verus to a tmp directory (unless VERUS_PATH is specified)
-    let temp_dir = tempdir::TempDir::new("verus")?;
-    let verus_path = if let Ok(path) = env::var("VERUS_PATH") {
-        path
-    } else {
-        extract_dir(&VERUS_TARGET, temp_dir.path())?;
-
-        set_executable(&temp_dir.path().join("verus"))?;
-        set_executable(&temp_dir.path().join("rust_verify"))?;
-        set_executable(&temp_dir.path().join("z3"))?;
+    let mut args: Vec<String> = args.collect();
 
-        temp_dir.path().join("verus").to_str()
-            .context("Invalid character in verus path")?.to_string()
+    // `vargo +<toolchain> ...` selects a toolchain for this invocation only
+    let toolchain_override = match args.first() {
+        Some(arg) if arg.starts_with('+') => Some(args.remove(0)[1..].to_string()),
+        _ => None,
     };
 
+    // `vargo toolchain ...` manages the local toolchain store
+    if args.first().map(String::as_str) == Some("toolchain") {
+        return toolchain::toolchain_command(&args[1..]);
+    }
+
+    // `vargo doctor` diagnoses the environment (including problems resolving Verus)
+    if args.first().map(String::as_str) == Some("doctor") {
+        return doctor::doctor_command(&args[1..], toolchain_override);
+    }
+
+    let (toolchain_name, verus_path) = resolve_verus(toolchain_override)?;
+
+    // `vargo fix-deps ...` pins the Verus dependencies to the selected toolchain
+    if args.first().map(String::as_str) == Some("fix-deps") {
+        return deps::fix_deps_command(&args[1..], toolchain_name.as_deref(), Path::new(&verus_path));
+    }
+
+    // `vargo new/init ...` creates Verus packages
+    if let Some(cmd @ ("new" | "init")) = args.first().map(String::as_str) {
+        return new::new_command(cmd, &args[1..], toolchain_name.as_deref(), Path::new(&verus_path));
+    }
+
     let exe_path = env::current_exe()
         .context("Failed to get the vargo executable path")?
         .to_str().context("Invalid character in the vargo executable path")?
         .to_string();
 
     // Overwrite some cargo commands
-    if let Some(cmd) = args.next() {
+    if let Some((cmd, rest)) = args.split_first() {
         // If the first command is exactly `verus` (i.e. `vargo verus` is called)
         // then we call verus directly
         if cmd == "verus" {
-            let verus_args: Vec<String> = args.collect();
             return Ok(Command::new(&verus_path)
-                .args(&verus_args)
+                .args(rest)
                 .status()
                 .context("Failed to run Verus")?
                 .code()
                 .unwrap_or(1));
         } else if cmd == "version" {
-            println!("vargo {}", env!("CARGO_PKG_VERSION"));
-
-            // Call Verus to get version
-            let verus_version = Command::new(&verus_path)
-                .arg("--version")
-                .output()
-                .context("Failed to get Verus version")?;
-            io::stdout().write_all(&verus_version.stdout)?;
-
-            // Finally call cargo version
-            let cargo_version = Command::new("cargo")
-                .arg("--version")
-                .output()
-                .context("Failed to get Cargo version")?;
-            io::stdout().write_all(&cargo_version.stdout)?;
-
-            return Ok(0);
+            return version::version_command(rest, &verus_path);
+        }
+    }
+
+    // `--verify-deps` (before any `--`) also verifies dependencies outside the workspace
+    let args_end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    let verify_deps = match args[..args_end].iter().position(|arg| arg == "--verify-deps") {
+        Some(pos) => {
+            args.remove(pos);
+            true
         }
+        None => verify_deps_enabled(),
+    };
+
+    // `--verus-flags <flags>` (before any `--`) overrides the other sources of Verus flags
+    let args_end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    let mut cli_verus_flags = None;
+    if let Some(pos) = args[..args_end].iter().position(|arg| arg == "--verus-flags" || arg.starts_with("--verus-flags=")) {
+        let arg = args.remove(pos);
+        cli_verus_flags = Some(match arg.strip_prefix("--verus-flags=") {
+            Some(flags) => flags.to_string(),
+            None if pos < args.len() => args.remove(pos),
+            None => bail!("Missing value for --verus-flags"),
+        });
+    }
+
+    let toolchain_dir = Path::new(&verus_path).parent()
+        .context("Failed to get the toolchain directory")?;
+    let is_build = cargo_subcommand(&args).is_some_and(|cmd| BUILD_COMMANDS.contains(&cmd));
+
+    // Make sure the project's vstd matches the selected Verus before building
+    if is_build {
+        deps::check_vstd_revision(toolchain_name.as_deref(), toolchain_dir, &args)?;
     }
 
+    // The crates being verified warn about their deprecated `[verus]` tables, except a virtual workspace root
+    if is_build {
+        if let Ok(manifest) = deps::workspace_manifest(deps::manifest_path_arg(&args).as_deref()) {
+            if config::read_settings(&manifest).is_ok_and(|settings| settings.deprecated && !settings.is_package) {
+                config::warn_deprecated(&manifest);
+            }
+        }
+    }
+
+    // Builds that verify dependencies keep their artifacts apart (in `<target dir>/verify-deps`),
+    // since cargo does not rebuild the dependencies when only the mode changes
+    // (outside of a cargo project, cargo reports the error)
+    if verify_deps && is_build && !has_target_dir_arg(&args) {
+        if let Ok(metadata) = deps::workspace_metadata(deps::manifest_path_arg(&args).as_deref()) {
+            let target_dir = metadata["target_directory"].as_str()
+                .context("Missing target_directory in cargo metadata")?;
+            let verify_deps_dir = Path::new(target_dir).join("verify-deps");
+            let verify_deps_dir = verify_deps_dir.to_str().context("Invalid character in the target directory")?;
+            let args_end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+            args.splice(args_end..args_end, ["--target-dir".to_string(), verify_deps_dir.to_string()]);
+        }
+    }
+
+    // Point the Verus dependencies to the sources shipped with the toolchain (e.g. for offline builds)
+    let patch_args = deps::local_vstd_patches(toolchain_dir, &args)?;
+
+    // Use the Rust toolchain that Verus was built with (via rustup)
+    let rust_toolchain_arg = toolchain::rust_toolchain_arg(toolchain_dir)?;
+
+    // Chain the wrappers configured before vargo (e.g. sccache), which vargo replaces
+    let inner_wrapper = wrapper::existing_wrapper(false);
+    let inner_workspace_wrapper = wrapper::existing_wrapper(true);
+
     // Defer the call to `cargo`
     let res = Command::new("cargo")
-        .env("RUSTC_WRAPPER", exe_path)
+        .args(&rust_toolchain_arg)
+        .args(&patch_args)
+        .envs(inner_wrapper.map(|inner| (wrapper::INNER_WRAPPER_VAR, inner.program)))
+        .envs(inner_workspace_wrapper.map(|inner| (wrapper::INNER_WORKSPACE_WRAPPER_VAR, inner.program)))
+        .env("RUSTC_WRAPPER", &exe_path)
+        // Marks workspace members, which are verified (dependencies are trusted by default)
+        .env("RUSTC_WORKSPACE_WRAPPER", &exe_path)
+        .env("VARGO_VERIFY_DEPS", if verify_deps { "true" } else { "false" })
+        .envs(cli_verus_flags.map(|flags| (CLI_VERUS_FLAGS_VAR, flags)))
         // A flag to indicate that all child process running vargo should be used as a RUSTC_WRAPPER
         // TODO: this is a bit hacky
         .env("VARGO_AS_RUSTC", "true")
         .env("VERUS_PATH", verus_path)
-        .args(env::args().skip(1))
+        .args(&args)
         .status()
         .context("Failed to run cargo")?
         .code()
         .unwrap_or(1);
 
-    drop(temp_dir);
     Ok(res)
 }
 
@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+
+use crate::deps;
+
+/// Library template with a single verified function
+const LIB_TEMPLATE: &str = "\
+use vstd::prelude::*;
+
+verus! {
+
+pub fn max(a: u64, b: u64) -> (res: u64)
+    ensures
+        res >= a,
+        res >= b,
+        res == a || res == b,
+{
+    if a >= b { a } else { b }
+}
+
+}
+";
+
+/// Binary template with a single verified function
+const BIN_TEMPLATE: &str = "\
+use vstd::prelude::*;
+
+verus! {
+
+fn max(a: u64, b: u64) -> (res: u64)
+    ensures
+        res >= a,
+        res >= b,
+        res == a || res == b,
+{
+    if a >= b { a } else { b }
+}
+
+}
+
+fn main() {
+    println!(\"max(1, 2) = {}\", max(1, 2));
+}
+";
+
+/// Spec crate template of a workspace (`vargo new --workspace`)
+const SPEC_TEMPLATE: &str = "\
+use vstd::prelude::*;
+
+verus! {
+
+pub open spec fn spec_max(a: int, b: int) -> int {
+    if a >= b { a } else { b }
+}
+
+}
+";
+
+/// Exec crate template of a workspace (`vargo new --workspace`),
+/// where `{spec_crate}` is replaced with the name of the spec crate
+const EXEC_TEMPLATE: &str = "\
+use vstd::prelude::*;
+
+#[allow(unused_imports)]
+use {spec_crate}::*;
+
+verus! {
+
+fn max(a: u64, b: u64) -> (res: u64)
+    ensures res == spec_max(a as int, b as int)
+{
+    if a >= b { a } else { b }
+}
+
+}
+
+fn main() {
+    println!(\"max(1, 2) = {}\", max(1, 2));
+}
+";
+
+/// Commented defaults of the Verus settings of a package
+const PACKAGE_VERUS_KEYS: &str = "\
+# Additional flags passed to Verus when verifying this crate
+# extra_flags = \"--rlimit 10\"
+# Whether to verify this crate (by default iff it depends on vstd)
+# verify = true
+# Additional crates that Verus provides itself (removed from the externs passed to Verus)
+# support_crates = []
+";
+
+/// Commented defaults of the Verus settings of a workspace root
+const WORKSPACE_VERUS_KEYS: &str = "\
+# Verus toolchain required by this workspace (see `vargo toolchain list`)
+# toolchain = \"embedded\"
+# What to do if the locked vstd does not match the embedded Verus (\"warn\", \"error\" or \"off\")
+# vstd_check = \"warn\"
+# Use the vstd sources shipped with the toolchain (by default only for offline builds)
+# local_vstd = false
+";
+
+/// Options of `cargo new`/`cargo init` that take a value
+const CARGO_NEW_VALUE_OPTIONS: [&str; 7] = ["--vcs", "--edition", "--name", "--registry", "--color", "--config", "-Z"];
+
+/// Finds the path argument of `cargo new`/`cargo init`
+fn path_argument(args: &[String]) -> Option<&String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if CARGO_NEW_VALUE_OPTIONS.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Runs `cargo <cmd> <args>`
+///
+/// The package is created for edition 2021 unless `--edition` is given, since the Rust toolchain
+/// of Verus may not support the default edition of the installed cargo.
+fn run_cargo(cmd: &str, args: &[String]) -> anyhow::Result<()> {
+    let has_edition = args.iter().any(|arg| arg == "--edition" || arg.starts_with("--edition="));
+    let status = Command::new("cargo")
+        .arg(cmd)
+        .args(args)
+        .args((!has_edition).then_some(["--edition", "2021"]).into_iter().flatten())
+        .status()
+        .context("Failed to run cargo")?;
+    if !status.success() {
+        bail!("cargo {} failed", cmd);
+    }
+    Ok(())
+}
+
+/// Name of the package in a Cargo.toml
+fn package_name(manifest: &Path) -> anyhow::Result<String> {
+    let content = fs::read_to_string(manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let parsed = toml::from_str::<toml::Value>(&content)
+        .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+    Ok(parsed.get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(|name| name.as_str())
+        .with_context(|| format!("Missing package name in {}", manifest.display()))?
+        .to_string())
+}
+
+/// Appends `text` to the file at `path`
+fn append_to_file(path: &Path, text: &str) -> anyhow::Result<()> {
+    let mut content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    content.push_str(text);
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Turns a freshly created cargo package at `dir` into a Verus package:
+/// adds the pinned Verus dependencies and a `[package.metadata.verus]` table, and writes `templates`
+/// (relative path and contents) to the package
+fn setup_package(dir: &Path, templates: &[(&str, String)], source: &deps::GitSource) -> anyhow::Result<()> {
+    let dir = dir.canonicalize()
+        .with_context(|| format!("Failed to find package directory {}", dir.display()))?;
+    let manifest = dir.join("Cargo.toml");
+    let name = package_name(&manifest)?;
+
+    // Also add the workspace-level settings if the package is its own workspace
+    let is_root = deps::workspace_manifest(Some(&manifest))?.canonicalize()? == manifest;
+    deps::pin_verus_dependencies(Some(&manifest), &[&name], true, source)?;
+
+    for (path, contents) in templates {
+        fs::write(dir.join(path), contents)
+            .with_context(|| format!("Failed to write {}", dir.join(path).display()))?;
+    }
+
+    append_to_file(&manifest, &format!(
+        "\n[package.metadata.verus]\n{}{}",
+        PACKAGE_VERUS_KEYS,
+        if is_root { WORKSPACE_VERUS_KEYS } else { "" },
+    ))?;
+
+    Ok(())
+}
+
+/// Creates a workspace at `dir` with a spec crate (`spec`) and an exec crate (`exec`)
+/// depending on it
+fn new_workspace(dir: &Path, cargo_args: &[String], source: &deps::GitSource) -> anyhow::Result<()> {
+    if dir.exists() {
+        bail!("Destination {} already exists", dir.display());
+    }
+
+    let name = dir.file_name().and_then(|name| name.to_str())
+        .context("Invalid character in the workspace path")?;
+    let spec_name = format!("{}-spec", name);
+    let exec_name = format!("{}-exec", name);
+
+    // Create the workspace root first so that cargo adds the crates to its (empty) members
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!("[workspace]\nmembers = []\n\nresolver = \"2\"\n\n[workspace.metadata.verus]\n{}", WORKSPACE_VERUS_KEYS),
+    )?;
+
+    for (subdir, package, kind) in [("spec", &spec_name, "--lib"), ("exec", &exec_name, "--bin")] {
+        let mut args = vec![
+            dir.join(subdir).to_str().context("Invalid character in the workspace path")?.to_string(),
+            kind.to_string(),
+            "--name".to_string(),
+            package.to_string(),
+        ];
+        args.extend(cargo_args.iter().cloned());
+        run_cargo("new", &args)?;
+    }
+
+    // The exec crate depends on the spec crate
+    let exec_manifest = dir.join("exec").join("Cargo.toml");
+    let mut doc = fs::read_to_string(&exec_manifest)?.parse::<toml_edit::DocumentMut>()?;
+    let mut dep = toml_edit::InlineTable::new();
+    dep.insert("path", "../spec".into());
+    doc["dependencies"][spec_name.as_str()] = toml_edit::value(dep);
+    fs::write(&exec_manifest, doc.to_string())?;
+
+    setup_package(&dir.join("spec"), &[("src/lib.rs", SPEC_TEMPLATE.to_string())], source)?;
+    setup_package(&dir.join("exec"), &[(
+        "src/main.rs",
+        EXEC_TEMPLATE.replace("{spec_crate}", &spec_name.replace('-', "_")),
+    )], source)?;
+
+    Ok(())
+}
+
+/// Entry point of `vargo new ...` and `vargo init ...`
+///
+/// Calls `cargo new`/`cargo init` and then sets up the Verus dependencies (pinned to the toolchain
+/// `toolchain_name`, if any, with the `verus` executable at `verus_path`) and templates.
+/// `vargo new --workspace <path>` instead creates a workspace with a spec and an exec crate.
+pub fn new_command(cmd: &str, args: &[String], toolchain_name: Option<&str>, verus_path: &Path) -> anyhow::Result<i32> {
+    let workspace = args.iter().any(|arg| arg == "--workspace");
+    let cargo_args: Vec<String> = args.iter().filter(|arg| *arg != "--workspace").cloned().collect();
+
+    let dir = match path_argument(&cargo_args) {
+        Some(path) => PathBuf::from(path),
+        None if cmd == "init" => PathBuf::from("."),
+        None => bail!("Usage: vargo new [--lib|--bin|--workspace] <path>"),
+    };
+
+    // Before creating anything, so that the package is not left half set up
+    let source = deps::verus_git_source(toolchain_name, verus_path);
+
+    if workspace {
+        if cmd != "new" {
+            bail!("--workspace is only supported by `vargo new`");
+        }
+        let path = dir.to_str().context("Invalid character in path")?;
+        let rest: Vec<String> = cargo_args.into_iter().filter(|arg| arg != path).collect();
+        new_workspace(&dir, &rest, &source)?;
+        return Ok(0);
+    }
+
+    // Only replace source files created by cargo (`cargo init` keeps existing ones)
+    let lib_path = dir.join("src").join("lib.rs");
+    let main_path = dir.join("src").join("main.rs");
+    let (lib_existed, main_existed) = (lib_path.exists(), main_path.exists());
+
+    run_cargo(cmd, &cargo_args)?;
+
+    let mut templates = Vec::new();
+    if !lib_existed && lib_path.exists() {
+        templates.push(("src/lib.rs", LIB_TEMPLATE.to_string()));
+    }
+    if !main_existed && main_path.exists() {
+        templates.push(("src/main.rs", BIN_TEMPLATE.to_string()));
+    }
+    setup_package(&dir, &templates, &source)?;
+
+    Ok(0)
+}
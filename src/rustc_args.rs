@@ -0,0 +1,333 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::Context;
+
+/// Options that take a value, either as the next argument or joined (`--opt=value`, or `-Ovalue` for short ones),
+/// other than the ones modeled by [`Arg`]
+const VALUE_OPTIONS: [&str; 26] = [
+    "-l", "--cfg", "--check-cfg", "--crate-type", "--print", "-o", "--explain", "--target",
+    "-A", "--allow", "-W", "--warn", "--force-warn", "-D", "--deny", "-F", "--forbid", "--cap-lints",
+    "-Z", "--sysroot", "--error-format", "--json", "--color", "--remap-path-prefix", "--diagnostic-width",
+    "--env-set",
+];
+
+/// An `--extern [<modifiers>:]<name>[=<path>]` argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extern {
+    /// e.g. `noprelude` or `priv`
+    pub modifiers: Vec<String>,
+    pub name: String,
+    pub path: Option<String>,
+}
+
+impl Extern {
+    fn parse(value: &str) -> Extern {
+        let (spec, path) = match value.split_once('=') {
+            Some((spec, path)) => (spec, Some(path.to_string())),
+            None => (value, None),
+        };
+        let (modifiers, name) = match spec.rsplit_once(':') {
+            Some((modifiers, name)) => (modifiers.split(',').map(str::to_string).collect(), name),
+            None => (Vec::new(), spec),
+        };
+        Extern { modifiers, name: name.to_string(), path }
+    }
+
+    /// The value of the `--extern` argument
+    pub fn value(&self) -> String {
+        let mut value = String::new();
+        if !self.modifiers.is_empty() {
+            value.push_str(&self.modifiers.join(","));
+            value.push(':');
+        }
+        value.push_str(&self.name);
+        if let Some(path) = &self.path {
+            value.push('=');
+            value.push_str(path);
+        }
+        value
+    }
+}
+
+/// A rustc argument (with its value, if any)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    Extern(Extern),
+    /// `-C <key>[=<value>]`
+    Codegen { key: String, value: Option<String> },
+    OutDir(String),
+    Edition(String),
+    Emit(String),
+    CrateName(String),
+    /// `-L [<kind>=]<path>`
+    SearchPath { kind: Option<String>, path: String },
+    /// Any other argument, kept as given (an option with its value, or the input file)
+    Other(Vec<String>),
+}
+
+impl Arg {
+    /// The arguments to pass this on to rustc (or Verus)
+    pub fn to_args(&self) -> Vec<String> {
+        match self {
+            Arg::Extern(ext) => vec!["--extern".to_string(), ext.value()],
+            Arg::Codegen { key, value: Some(value) } => vec!["-C".to_string(), format!("{}={}", key, value)],
+            Arg::Codegen { key, value: None } => vec!["-C".to_string(), key.clone()],
+            Arg::OutDir(dir) => vec!["--out-dir".to_string(), dir.clone()],
+            Arg::Edition(edition) => vec![format!("--edition={}", edition)],
+            Arg::Emit(emit) => vec![format!("--emit={}", emit)],
+            Arg::CrateName(name) => vec!["--crate-name".to_string(), name.clone()],
+            Arg::SearchPath { kind: Some(kind), path } => vec!["-L".to_string(), format!("{}={}", kind, path)],
+            Arg::SearchPath { kind: None, path } => vec!["-L".to_string(), path.clone()],
+            Arg::Other(args) => args.clone(),
+        }
+    }
+}
+
+/// Kinds of `-L <kind>=<path>` search paths
+const SEARCH_PATH_KINDS: [&str; 5] = ["dependency", "crate", "native", "framework", "all"];
+
+/// Whether the arguments include `@<path>` argument files
+pub fn has_argfile(args: &[String]) -> bool {
+    args.iter().any(|arg| arg.starts_with('@'))
+}
+
+/// Writes arguments to an argument file (passed as `@<path>`), one per line
+pub fn write_argfile(path: &Path, args: &[String]) -> io::Result<()> {
+    if args.iter().any(|arg| arg.contains('\n')) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "argument containing a newline"));
+    }
+    fs::write(path, args.join("\n"))
+}
+
+/// Expands `@<path>` arguments (which cargo uses for long command lines) into the arguments
+/// in the file, one per line
+fn expand_argfiles(args: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('@') {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read rustc argument file {}", path))?;
+                expanded.extend(content.lines().map(str::to_string));
+            }
+            None => expanded.push(arg.clone()),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Parses rustc arguments, including the joined forms (`--extern=...`, `-Cmetadata=...`, `--out-dir=...`)
+/// and `@<path>` argument files
+pub fn parse(args: &[String]) -> anyhow::Result<Vec<Arg>> {
+    let args = expand_argfiles(args)?;
+    let mut parsed = Vec::new();
+    let mut iter = args.into_iter();
+
+    while let Some(arg) = iter.next() {
+        // Split `--opt=value` and `-Ovalue`
+        let (opt, joined) = if arg.starts_with("--") {
+            match arg.split_once('=') {
+                Some((opt, value)) => (opt.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            }
+        } else if let Some((i, _)) = arg.char_indices().nth(2).filter(|_| arg.starts_with('-')) {
+            let (opt, value) = arg.split_at(i);
+            (opt.to_string(), Some(value.to_string()))
+        } else {
+            (arg.clone(), None)
+        };
+
+        let modeled = matches!(
+            opt.as_str(),
+            "--extern" | "-C" | "--codegen" | "--out-dir" | "--edition" | "--emit" | "--crate-name" | "-L"
+        );
+        if !modeled && !VALUE_OPTIONS.contains(&opt.as_str()) {
+            parsed.push(Arg::Other(vec![arg]));
+            continue;
+        }
+
+        let value = match joined {
+            Some(value) => value,
+            None => iter.next().with_context(|| format!("Missing value for rustc option {}", opt))?,
+        };
+
+        parsed.push(match opt.as_str() {
+            "--extern" => Arg::Extern(Extern::parse(&value)),
+            "-C" | "--codegen" => match value.split_once('=') {
+                Some((key, value)) => Arg::Codegen { key: key.to_string(), value: Some(value.to_string()) },
+                None => Arg::Codegen { key: value, value: None },
+            },
+            "--out-dir" => Arg::OutDir(value),
+            "--edition" => Arg::Edition(value),
+            "--emit" => Arg::Emit(value),
+            "--crate-name" => Arg::CrateName(value),
+            // Only a known kind is split off (a path may contain `=` too)
+            "-L" => match value.split_once('=') {
+                Some((kind, path)) if SEARCH_PATH_KINDS.contains(&kind) => {
+                    Arg::SearchPath { kind: Some(kind.to_string()), path: path.to_string() }
+                }
+                _ => Arg::SearchPath { kind: None, path: value },
+            },
+            _ if arg == opt => Arg::Other(vec![arg, value]),
+            _ => Arg::Other(vec![arg]),
+        });
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ext, strings};
+
+    fn codegen(key: &str, value: Option<&str>) -> Arg {
+        Arg::Codegen { key: key.to_string(), value: value.map(str::to_string) }
+    }
+
+    fn search_path(kind: Option<&str>, path: &str) -> Arg {
+        Arg::SearchPath { kind: kind.map(str::to_string), path: path.to_string() }
+    }
+
+    fn other(args: &[&str]) -> Arg {
+        Arg::Other(strings(args))
+    }
+
+    #[test]
+    fn parse_forms() {
+        let cases: Vec<(&[&str], Arg)> = vec![
+            (&["--extern", "a=/d/liba-1.rlib"], Arg::Extern(ext(&[], "a", Some("/d/liba-1.rlib")))),
+            (&["--extern=a=/d/liba-1.rlib"], Arg::Extern(ext(&[], "a", Some("/d/liba-1.rlib")))),
+            (&["--extern", "a"], Arg::Extern(ext(&[], "a", None))),
+            (&["--extern", "noprelude:a=/d/liba-1.rlib"], Arg::Extern(ext(&["noprelude"], "a", Some("/d/liba-1.rlib")))),
+            (&["--extern", "priv:a=/d/liba-1.rlib"], Arg::Extern(ext(&["priv"], "a", Some("/d/liba-1.rlib")))),
+            (&["--extern", "priv,noprelude:a=/d/liba-1.rlib"], Arg::Extern(ext(&["priv", "noprelude"], "a", Some("/d/liba-1.rlib")))),
+            (&["--extern=noprelude:a"], Arg::Extern(ext(&["noprelude"], "a", None))),
+            (&["--extern", "a=C:/d/liba-1.rlib"], Arg::Extern(ext(&[], "a", Some("C:/d/liba-1.rlib")))),
+            (&["-C", "metadata=abc"], codegen("metadata", Some("abc"))),
+            (&["-Cmetadata=abc"], codegen("metadata", Some("abc"))),
+            (&["--codegen", "metadata=abc"], codegen("metadata", Some("abc"))),
+            (&["--codegen=metadata=abc"], codegen("metadata", Some("abc"))),
+            (&["-C", "extra-filename=-abc"], codegen("extra-filename", Some("-abc"))),
+            (&["-C", "debug-assertions"], codegen("debug-assertions", None)),
+            (&["--out-dir", "/t/deps"], Arg::OutDir("/t/deps".to_string())),
+            (&["--out-dir=/t/deps"], Arg::OutDir("/t/deps".to_string())),
+            (&["--edition", "2021"], Arg::Edition("2021".to_string())),
+            (&["--edition=2021"], Arg::Edition("2021".to_string())),
+            (&["--emit", "dep-info,link"], Arg::Emit("dep-info,link".to_string())),
+            (&["--emit=dep-info,link"], Arg::Emit("dep-info,link".to_string())),
+            (&["--crate-name", "a"], Arg::CrateName("a".to_string())),
+            (&["--crate-name=a"], Arg::CrateName("a".to_string())),
+            (&["-L", "dependency=/t/deps"], search_path(Some("dependency"), "/t/deps")),
+            (&["-Ldependency=/t/deps"], search_path(Some("dependency"), "/t/deps")),
+            (&["-L", "/t/deps"], search_path(None, "/t/deps")),
+            (&["-L", "native=/t/out"], search_path(Some("native"), "/t/out")),
+            (&["-L", "all=/t/out"], search_path(Some("all"), "/t/out")),
+            (&["-L", "/t/a=b/deps"], search_path(None, "/t/a=b/deps")),
+            (&["-L", "dependency=/t/a=b"], search_path(Some("dependency"), "/t/a=b")),
+            (&["-Lé=/t"], search_path(None, "é=/t")),
+            (&["-é"], other(&["-é"])),
+            (&["-éé"], other(&["-éé"])),
+            (&["--cfg", "feature=\"std\""], other(&["--cfg", "feature=\"std\""])),
+            (&["--cfg=feature=\"std\""], other(&["--cfg=feature=\"std\""])),
+            (&["--crate-type", "lib"], other(&["--crate-type", "lib"])),
+            (&["-A", "warnings"], other(&["-A", "warnings"])),
+            (&["--cap-lints", "allow"], other(&["--cap-lints", "allow"])),
+            (&["--error-format=json"], other(&["--error-format=json"])),
+            (&["-Zunstable-options"], other(&["-Zunstable-options"])),
+            (&["-g"], other(&["-g"])),
+            (&["src/lib.rs"], other(&["src/lib.rs"])),
+            (&["-"], other(&["-"])),
+        ];
+
+        for (args, expected) in cases {
+            assert_eq!(parse(&strings(args)).unwrap(), vec![expected], "parsing {:?}", args);
+        }
+    }
+
+    #[test]
+    fn option_values_are_not_parsed_as_options() {
+        let cases: Vec<(&[&str], Vec<Arg>)> = vec![
+            (&["--cfg", "--extern", "-g"], vec![other(&["--cfg", "--extern"]), other(&["-g"])]),
+            (&["-l", "-C", "--out-dir", "/t"], vec![other(&["-l", "-C"]), Arg::OutDir("/t".to_string())]),
+        ];
+
+        for (args, expected) in cases {
+            assert_eq!(parse(&strings(args)).unwrap(), expected, "parsing {:?}", args);
+        }
+    }
+
+    #[test]
+    fn missing_values() {
+        let cases: &[&[&str]] = &[&["--extern"], &["-C"], &["--out-dir"], &["--edition"], &["-L"], &["--cfg"]];
+
+        for args in cases {
+            assert!(parse(&strings(args)).is_err(), "parsing {:?}", args);
+        }
+    }
+
+    #[test]
+    fn argfiles() {
+        let dir = tempdir::TempDir::new("vargo-rustc-args").unwrap();
+        let argfile = dir.path().join("args");
+        fs::write(&argfile, "--extern\na=/d/liba-1.rlib\n-Cmetadata=abc\n").unwrap();
+
+        let args = strings(&["--crate-name", "b", &format!("@{}", argfile.display()), "src/lib.rs"]);
+        let parsed = parse(&args).unwrap();
+
+        // Written argument files are read back as the same arguments
+        let written = strings(&["--extern", "a=/d/a b/liba-1.rlib", "--cfg", "feature=\"std\"", "src/lib.rs"]);
+        let verus_argfile = dir.path().join("verus-args");
+        write_argfile(&verus_argfile, &written).unwrap();
+        assert_eq!(expand_argfiles(&[format!("@{}", verus_argfile.display())]).unwrap(), written);
+        assert!(write_argfile(&verus_argfile, &strings(&["a\nb"])).is_err());
+
+        assert_eq!(parsed, vec![
+            Arg::CrateName("b".to_string()),
+            Arg::Extern(ext(&[], "a", Some("/d/liba-1.rlib"))),
+            codegen("metadata", Some("abc")),
+            other(&["src/lib.rs"]),
+        ]);
+        assert!(parse(&strings(&["@/nonexistent/vargo-args"])).is_err());
+    }
+
+    #[test]
+    fn to_args() {
+        let cases: Vec<(Arg, &[&str])> = vec![
+            (Arg::Extern(ext(&[], "a", Some("/d/liba-1.rlib"))), &["--extern", "a=/d/liba-1.rlib"]),
+            (Arg::Extern(ext(&["priv", "noprelude"], "a", None)), &["--extern", "priv,noprelude:a"]),
+            (codegen("metadata", Some("abc")), &["-C", "metadata=abc"]),
+            (codegen("debug-assertions", None), &["-C", "debug-assertions"]),
+            (Arg::OutDir("/t/deps".to_string()), &["--out-dir", "/t/deps"]),
+            (Arg::Edition("2021".to_string()), &["--edition=2021"]),
+            (Arg::Emit("metadata".to_string()), &["--emit=metadata"]),
+            (Arg::CrateName("a".to_string()), &["--crate-name", "a"]),
+            (search_path(Some("dependency"), "/t/deps"), &["-L", "dependency=/t/deps"]),
+            (search_path(None, "/t/deps"), &["-L", "/t/deps"]),
+            (other(&["--cfg", "test"]), &["--cfg", "test"]),
+        ];
+
+        for (arg, expected) in cases {
+            assert_eq!(arg.to_args(), strings(expected), "emitting {:?}", arg);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases: &[&[&str]] = &[
+            &["--extern=noprelude:a=/d/liba-1.rlib"],
+            &["-Cmetadata=abc"],
+            &["--out-dir=/t/deps"],
+            &["--codegen", "opt-level=3"],
+            &["-Lall=/t/deps"],
+        ];
+
+        for args in cases {
+            let parsed = parse(&strings(args)).unwrap();
+            let emitted: Vec<String> = parsed.iter().flat_map(Arg::to_args).collect();
+            assert_eq!(parse(&emitted).unwrap(), parsed, "round trip of {:?}", args);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Helpers shared by the unit tests
+
+use crate::rustc_args::Extern;
+
+pub fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+pub fn ext(modifiers: &[&str], name: &str, path: Option<&str>) -> Extern {
+    Extern {
+        modifiers: strings(modifiers),
+        name: name.to_string(),
+        path: path.map(str::to_string),
+    }
+}
@@ -0,0 +1,685 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context};
+use fs2::FileExt;
+
+use flate2::read::GzDecoder;
+
+use crate::common::copy_dir;
+use crate::config;
+use crate::version::EMBEDDED_PROVENANCE;
+
+/// Gzip-compressed tarball of the Verus toolchain (packed in build.rs)
+#[cfg(feature = "embedded-verus")]
+static VERUS_ARCHIVE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/verus.tar.gz"));
+
+/// Hash of the embedded toolchain archive (computed in build.rs)
+#[cfg(feature = "embedded-verus")]
+const VERUS_ARCHIVE_HASH: &str = env!("VARGO_EMBEDDED_HASH");
+
+/// Name of the built-in toolchain packed into the vargo executable
+pub const EMBEDDED_TOOLCHAIN: &str = "embedded";
+
+/// Unpacks a tarball to `dest`, restoring file modes and symlinks
+fn unpack_tarball(reader: impl Read, dest: &Path) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    archive.unpack(dest)
+}
+
+/// Directory for vargo's per-user state: `$VARGO_HOME` if set, otherwise `~/.vargo`
+pub fn vargo_home() -> anyhow::Result<PathBuf> {
+    if let Ok(home) = env::var("VARGO_HOME") {
+        return Ok(PathBuf::from(home));
+    }
+
+    let home = env::var("HOME")
+        .context("Failed to locate the home directory (set VARGO_HOME or HOME)")?;
+    Ok(Path::new(&home).join(".vargo"))
+}
+
+/// Populates `dest` (if it does not exist yet) by calling `fill` on a staging directory
+/// and then renaming the staging directory into place
+///
+/// Concurrent vargo processes are serialized on a lock file next to `dest`,
+/// so `dest` is always either absent or complete.
+fn install_atomically(
+    dest: &Path,
+    fill: impl FnOnce(&Path) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let parent = dest.parent().context("Install destination has no parent directory")?;
+    let name = dest.file_name().and_then(|name| name.to_str())
+        .context("Invalid character in install destination")?;
+
+    fs::create_dir_all(parent)
+        .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+
+    let lock_path = parent.join(format!(".{}.lock", name));
+    let lock = File::create(&lock_path)
+        .with_context(|| format!("Failed to create lock file {}", lock_path.display()))?;
+    lock.lock_exclusive()
+        .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+
+    // Another process might have finished the installation while we were waiting
+    if !dest.is_dir() {
+        // Left-over staging directories are from interrupted runs (we hold the lock)
+        let staging_dir = parent.join(format!(".{}.partial", name));
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+
+        fill(&staging_dir)?;
+
+        fs::rename(&staging_dir, dest)
+            .with_context(|| format!("Failed to install toolchain to {}", dest.display()))?;
+    }
+
+    lock.unlock()?;
+    Ok(())
+}
+
+/// Extracts the embedded Verus toolchain (if not done already) to
+/// `<vargo home>/embedded/<content hash>` and returns that directory
+#[cfg(feature = "embedded-verus")]
+pub fn embedded_toolchain() -> anyhow::Result<PathBuf> {
+    let toolchain_dir = vargo_home()?.join("embedded").join(VERUS_ARCHIVE_HASH);
+
+    // Fast path: already extracted by a previous run
+    if !toolchain_dir.is_dir() {
+        install_atomically(&toolchain_dir, |staging_dir| {
+            unpack_tarball(GzDecoder::new(VERUS_ARCHIVE), staging_dir)
+                .context("Failed to extract the embedded Verus toolchain")?;
+
+            // The toolchains embedded in previous builds of vargo are not used anymore
+            let embedded_dir = staging_dir.parent().context("Install destination has no parent directory")?;
+            if let Err(err) = remove_stale_embedded(embedded_dir, VERUS_ARCHIVE_HASH) {
+                crate::cargo_warning(&format!("failed to remove old embedded Verus toolchains: {:#}", err));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(toolchain_dir)
+}
+
+/// Removes the toolchains in `embedded_dir` extracted from archives other than `current`
+/// (skipping those that another vargo is extracting)
+#[cfg(feature = "embedded-verus")]
+fn remove_stale_embedded(embedded_dir: &Path, current: &str) -> anyhow::Result<()> {
+    for entry in fs::read_dir(embedded_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let is_hash = name.len() == current.len() && name.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hash || name == current || !path.is_dir() {
+            continue;
+        }
+
+        let lock_path = embedded_dir.join(format!(".{}.lock", name));
+        let lock = File::create(&lock_path)
+            .with_context(|| format!("Failed to create lock file {}", lock_path.display()))?;
+        if lock.try_lock_exclusive().is_err() {
+            continue;
+        }
+        fs::remove_dir_all(&path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        fs::remove_file(&lock_path)?;
+    }
+    Ok(())
+}
+
+/// Without the `embedded-verus` feature, vargo has no built-in toolchain
+#[cfg(not(feature = "embedded-verus"))]
+pub fn embedded_toolchain() -> anyhow::Result<PathBuf> {
+    bail!("This vargo was built without an embedded Verus toolchain (the `embedded-verus` feature)");
+}
+
+/// Whether vargo was built with the embedded Verus toolchain
+pub const HAS_EMBEDDED_TOOLCHAIN: bool = cfg!(feature = "embedded-verus");
+
+/// Directory containing toolchains installed by `vargo toolchain install`
+fn toolchains_dir() -> anyhow::Result<PathBuf> {
+    Ok(vargo_home()?.join("toolchains"))
+}
+
+/// Path to the user-wide settings file
+fn settings_path() -> anyhow::Result<PathBuf> {
+    Ok(vargo_home()?.join("settings.toml"))
+}
+
+/// Reads the user-wide settings (empty if the file does not exist)
+fn read_settings() -> anyhow::Result<toml::Table> {
+    let path = settings_path()?;
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Name of the default toolchain (the embedded one unless set by `vargo toolchain default`;
+/// `None` if neither exists)
+pub fn default_toolchain() -> anyhow::Result<Option<String>> {
+    Ok(read_settings()?
+        .get("default_toolchain")
+        .and_then(|name| name.as_str())
+        .or(HAS_EMBEDDED_TOOLCHAIN.then_some(EMBEDDED_TOOLCHAIN))
+        .map(str::to_string))
+}
+
+/// Finds `verus` on `PATH` (used when there is no embedded or default toolchain)
+pub fn verus_on_path() -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join("verus"))
+        .find(|path| path.is_file())
+}
+
+/// Rust toolchain that a Verus toolchain was built with: the `channel` of the
+/// `rust-toolchain.toml` shipped with it (recorded in build.rs), or the `toolchain` recorded
+/// in the `version.json` of official Verus releases
+pub fn required_rust_toolchain(toolchain_dir: &Path) -> anyhow::Result<Option<String>> {
+    let rust_toolchain_file = toolchain_dir.join("rust-toolchain.toml");
+    if rust_toolchain_file.is_file() {
+        let content = fs::read_to_string(&rust_toolchain_file)
+            .with_context(|| format!("Failed to read {}", rust_toolchain_file.display()))?;
+        let parsed = toml::from_str::<toml::Table>(&content)
+            .with_context(|| format!("Failed to parse {}", rust_toolchain_file.display()))?;
+        return Ok(parsed.get("toolchain")
+            .and_then(|toolchain| toolchain.get("channel"))
+            .and_then(|channel| channel.as_str())
+            .map(str::to_string));
+    }
+
+    let version_file = toolchain_dir.join("version.json");
+    if version_file.is_file() {
+        let content = fs::read_to_string(&version_file)
+            .with_context(|| format!("Failed to read {}", version_file.display()))?;
+        let parsed = serde_json::from_str::<serde_json::Value>(&content)
+            .with_context(|| format!("Failed to parse {}", version_file.display()))?;
+        return Ok(parsed["verus"]["toolchain"].as_str().map(str::to_string));
+    }
+
+    Ok(None)
+}
+
+/// The `verus` section of the `version.json` of official Verus releases (if any)
+fn release_info(toolchain_dir: &Path) -> Option<serde_json::Value> {
+    let version = fs::read_to_string(toolchain_dir.join("version.json")).ok()?;
+    let mut version = serde_json::from_str::<serde_json::Value>(&version).ok()?;
+    Some(version["verus"].take())
+}
+
+/// Verus commit of a toolchain, from the `version.json` of official Verus releases (if any)
+pub fn toolchain_commit(toolchain_dir: &Path) -> Option<String> {
+    let info = release_info(toolchain_dir)?;
+    ["commit", "sha"].into_iter()
+        .find_map(|key| info[key].as_str())
+        .map(str::to_string)
+}
+
+/// Verus commit of the selected toolchain (if known): the provenance recorded at build time
+/// for the embedded toolchain, and the `version.json` of official releases otherwise
+pub fn selected_toolchain_commit(toolchain_name: Option<&str>, toolchain_dir: &Path) -> Option<String> {
+    if toolchain_name == Some(EMBEDDED_TOOLCHAIN) {
+        return Some(EMBEDDED_PROVENANCE.commit.to_string()).filter(|commit| !commit.is_empty());
+    }
+    toolchain_commit(toolchain_dir)
+}
+
+/// Verus version of a toolchain, from the `version.json` of official Verus releases (if any)
+pub fn toolchain_version(toolchain_dir: &Path) -> Option<String> {
+    release_info(toolchain_dir)?["version"].as_str().map(str::to_string)
+}
+
+/// Rust toolchains installed with rustup (`None` if rustup is not available)
+fn rustup_toolchains() -> Option<Vec<String>> {
+    let output = Command::new("rustup").args(["toolchain", "list"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Lines look like `1.82.0-x86_64-unknown-linux-gnu (active, default)`
+    Some(String::from_utf8_lossy(&output.stdout).lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Returns the `+<toolchain>` argument to run cargo with the Rust toolchain required by
+/// the Verus toolchain at `toolchain_dir`, if it requires one and rustup is available
+///
+/// Otherwise the `rmeta` files produced by Verus and by rustc would come from different compilers.
+/// Set `VARGO_RUST_TOOLCHAIN=off` to use the Rust toolchain selected as usual instead.
+pub fn rust_toolchain_arg(toolchain_dir: &Path) -> anyhow::Result<Option<String>> {
+    if env::var("VARGO_RUST_TOOLCHAIN").as_deref() == Ok("off") {
+        return Ok(None);
+    }
+
+    let Some(required) = required_rust_toolchain(toolchain_dir)? else {
+        return Ok(None);
+    };
+    let Some(installed) = rustup_toolchains() else {
+        return Ok(None);
+    };
+
+    // A channel like `1.82.0` matches `1.82.0-<host triple>`
+    let is_installed = installed.iter().any(|name| {
+        name == &required || name.strip_prefix(required.as_str()).is_some_and(|rest| rest.starts_with('-'))
+    });
+    if !is_installed {
+        bail!(
+            "The Verus toolchain at {} requires Rust toolchain `{}`, which is not installed; \
+            install it with `rustup toolchain install {}` (or set VARGO_RUST_TOOLCHAIN=off to use the current one)",
+            toolchain_dir.display(), required, required,
+        );
+    }
+
+    Ok(Some(format!("+{}", required)))
+}
+
+/// Names of the toolchains that can be selected: the embedded one (if any) and the installed ones
+fn available_toolchains() -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if HAS_EMBEDDED_TOOLCHAIN {
+        names.push(EMBEDDED_TOOLCHAIN.to_string());
+    }
+    names.extend(installed_toolchains()?);
+    Ok(names)
+}
+
+/// Names of all toolchains in the local store (not including the embedded one)
+pub fn installed_toolchains() -> anyhow::Result<Vec<String>> {
+    let dir = toolchains_dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if let Some(name) = entry.file_name().to_str() {
+            // Skip staging directories and lock files
+            if entry.file_type()?.is_dir() && !name.starts_with('.') {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the directory of the toolchain `name`
+pub fn toolchain_dir(name: &str) -> anyhow::Result<PathBuf> {
+    if name == EMBEDDED_TOOLCHAIN {
+        return embedded_toolchain();
+    }
+    check_toolchain_name(name)?;
+
+    let dir = toolchains_dir()?.join(name);
+    if !dir.is_dir() {
+        let available = available_toolchains()?;
+        bail!(
+            "Verus toolchain `{}` is not installed (available: {}); install it with `vargo toolchain install`",
+            name, available.join(", "),
+        );
+    }
+
+    Ok(dir)
+}
+
+/// Name of the file pinning the toolchain of a project
+const TOOLCHAIN_FILE: &str = "verus-toolchain.toml";
+
+/// The Verus toolchain a project requires
+pub enum ToolchainPin {
+    /// A toolchain by name
+    Name(String),
+    /// Any toolchain built from this Verus commit (or a prefix of it)
+    Commit(String),
+    /// Any toolchain of this Verus release version
+    Version(String),
+}
+
+impl std::fmt::Display for ToolchainPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ToolchainPin::Name(name) => write!(f, "toolchain `{}`", name),
+            ToolchainPin::Commit(commit) => write!(f, "commit `{}`", commit),
+            ToolchainPin::Version(version) => write!(f, "version `{}`", version),
+        }
+    }
+}
+
+impl ToolchainPin {
+    /// Parses a toolchain name, or a table with exactly one of `name`, `commit` or `version`
+    pub fn parse(value: &toml::Value, path: &Path) -> anyhow::Result<ToolchainPin> {
+        if let Some(name) = value.as_str() {
+            return Ok(ToolchainPin::Name(name.to_string()));
+        }
+
+        let table = value.as_table()
+            .with_context(|| format!("Expected a toolchain name or table in {}", path.display()))?;
+        let mut keys = table.iter();
+        let (Some((key, value)), None) = (keys.next(), keys.next()) else {
+            bail!("Expected exactly one of `name`, `commit` or `version` for the toolchain in {}", path.display());
+        };
+        let value = value.as_str()
+            .with_context(|| format!("Expected a string for the toolchain `{}` in {}", key, path.display()))?
+            .to_string();
+
+        match key.as_str() {
+            "name" => Ok(ToolchainPin::Name(value)),
+            "commit" => Ok(ToolchainPin::Commit(value)),
+            "version" => Ok(ToolchainPin::Version(value)),
+            _ => bail!("Unknown toolchain key `{}` in {} (expected `name`, `commit` or `version`)", key, path.display()),
+        }
+    }
+
+    /// Name of the toolchain satisfying the pin: the pinned name if it is available,
+    /// otherwise the first available toolchain with the pinned commit or version
+    pub fn resolve(&self) -> anyhow::Result<String> {
+        if let ToolchainPin::Name(name) = self {
+            toolchain_dir(name)?;
+            return Ok(name.clone());
+        }
+
+        let mut available = Vec::new();
+        for name in available_toolchains()? {
+            let dir = toolchain_dir(&name)?;
+            let commit = match toolchain_commit(&dir) {
+                None if name == EMBEDDED_TOOLCHAIN => {
+                    Some(EMBEDDED_PROVENANCE.commit.to_string()).filter(|commit| !commit.is_empty())
+                }
+                commit => commit,
+            };
+            let version = toolchain_version(&dir);
+
+            let matches = match self {
+                ToolchainPin::Commit(pinned) => {
+                    !pinned.is_empty() && commit.as_deref().is_some_and(|commit| commit.starts_with(pinned.as_str()))
+                }
+                ToolchainPin::Version(pinned) => version.as_deref() == Some(pinned.as_str()),
+                ToolchainPin::Name(_) => unreachable!(),
+            };
+            if matches {
+                return Ok(name);
+            }
+
+            let provenance = [commit.map(|commit| format!("commit {}", commit)), version.map(|version| format!("version {}", version))];
+            let provenance: Vec<String> = provenance.into_iter().flatten().collect();
+            available.push(if provenance.is_empty() {
+                name
+            } else {
+                format!("{} ({})", name, provenance.join(", "))
+            });
+        }
+
+        bail!(
+            "No Verus toolchain with {} is available (available: {}); install it with `vargo toolchain install`",
+            self, available.join(", "),
+        );
+    }
+}
+
+/// Reads the toolchain pinned in a `verus-toolchain.toml`:
+/// ```toml
+/// [toolchain]
+/// name = "<toolchain name>"  # or `commit = "<Verus commit>"`, or `version = "<Verus version>"`
+/// ```
+/// or in the Verus settings of a Cargo.toml:
+/// ```toml
+/// [workspace.metadata.verus]  # or [package.metadata.verus]
+/// toolchain = "<toolchain name>"  # or `{ commit = "<Verus commit>" }`, or `{ version = "<Verus version>" }`
+/// ```
+fn read_pinned_toolchain(path: &Path) -> anyhow::Result<Option<ToolchainPin>> {
+    let pinned = if path.ends_with(TOOLCHAIN_FILE) {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let parsed = toml::from_str::<toml::Value>(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        parsed.get("toolchain").cloned()
+    } else {
+        config::read_settings(path)?.get("toolchain").cloned()
+    };
+
+    pinned.map(|pinned| ToolchainPin::parse(&pinned, path)).transpose()
+}
+
+/// The root manifest of the workspace containing `dir`: like cargo, the closest Cargo.toml
+/// with a `[workspace]` table, or else the closest Cargo.toml (a package outside any workspace)
+fn workspace_root_manifest(dir: &Path) -> Option<PathBuf> {
+    let manifests: Vec<PathBuf> = dir.ancestors()
+        .map(|dir| dir.join("Cargo.toml"))
+        .filter(|manifest| manifest.is_file())
+        .collect();
+    manifests.iter()
+        .find(|manifest| config::read_settings(manifest).is_ok_and(|settings| settings.is_workspace_root))
+        .or(manifests.first())
+        .cloned()
+}
+
+/// Finds the toolchain pinned by the project containing the current directory,
+/// returning the pin and the file pinning it
+///
+/// Each directory from the current one upwards is checked for a `verus-toolchain.toml`
+/// and, in the workspace root, for a `toolchain` key in the Verus settings of its Cargo.toml;
+/// the nearest one wins.
+pub fn pinned_toolchain() -> anyhow::Result<Option<(ToolchainPin, PathBuf)>> {
+    let cwd = env::current_dir().context("Failed to get the current directory")?;
+    let root_manifest = workspace_root_manifest(&cwd);
+
+    for dir in cwd.ancestors() {
+        let toolchain_file = dir.join(TOOLCHAIN_FILE);
+        let manifest = dir.join("Cargo.toml");
+        let candidates = [
+            Some(toolchain_file).filter(|path| path.is_file()),
+            Some(manifest).filter(|path| root_manifest.as_ref() == Some(path)),
+        ];
+        for path in candidates.into_iter().flatten() {
+            if let Some(pin) = read_pinned_toolchain(&path)? {
+                return Ok(Some((pin, path)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Checks that a toolchain name can be used as a directory name in the store
+fn check_toolchain_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.starts_with('+') || name.contains(['/', '\\']) {
+        bail!("Invalid toolchain name `{}`", name);
+    }
+    if name == EMBEDDED_TOOLCHAIN {
+        bail!("Toolchain name `{}` is reserved for the built-in toolchain", name);
+    }
+    Ok(())
+}
+
+/// If `dir` does not directly contain `verus` but has a single subdirectory
+/// (as in most release tarballs), returns that subdirectory instead
+fn find_toolchain_root(dir: &Path) -> anyhow::Result<PathBuf> {
+    if dir.join("verus").is_file() {
+        return Ok(dir.to_path_buf());
+    }
+
+    let entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    if let [entry] = entries.as_slice() {
+        if entry.file_type()?.is_dir() && entry.path().join("verus").is_file() {
+            return Ok(entry.path());
+        }
+    }
+
+    bail!("{} does not look like a Verus toolchain (no `verus` executable found)", dir.display())
+}
+
+/// Installs a toolchain from a directory, a (optionally gzip-compressed) tarball
+/// or a `.zip` archive (like official Verus releases) into the local store, returning the name it was installed under
+fn install_toolchain(source: &Path, name: Option<&str>, force: bool) -> anyhow::Result<String> {
+    let file_name = source.file_name().and_then(|name| name.to_str())
+        .context("Invalid character in toolchain path")?;
+    let is_archive = source.is_file();
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => [".tar.gz", ".tgz", ".tar", ".zip"].iter()
+            .find_map(|ext| file_name.strip_suffix(ext))
+            .unwrap_or(file_name)
+            .to_string(),
+    };
+    check_toolchain_name(&name)?;
+
+    let dest = toolchains_dir()?.join(&name);
+    if dest.exists() {
+        if !force {
+            bail!("Toolchain `{}` is already installed (use --force to replace it)", name);
+        }
+        fs::remove_dir_all(&dest)
+            .with_context(|| format!("Failed to remove {}", dest.display()))?;
+    }
+
+    install_atomically(&dest, |staging_dir| {
+        let unpack_dir = staging_dir.with_extension("unpack");
+        if unpack_dir.exists() {
+            fs::remove_dir_all(&unpack_dir)?;
+        }
+
+        if is_archive && file_name.ends_with(".zip") {
+            fs::create_dir_all(&unpack_dir)?;
+            let status = Command::new("unzip")
+                .args(["-q", "-o"]).arg(source).arg("-d").arg(&unpack_dir)
+                .status()
+                .context("Failed to run unzip")?;
+            if !status.success() {
+                bail!("Failed to unzip {}", source.display());
+            }
+        } else if is_archive {
+            let file = File::open(source)
+                .with_context(|| format!("Failed to open {}", source.display()))?;
+            if file_name.ends_with(".tar") {
+                unpack_tarball(file, &unpack_dir)
+            } else {
+                unpack_tarball(GzDecoder::new(file), &unpack_dir)
+            }.with_context(|| format!("Failed to unpack {}", source.display()))?;
+        } else {
+            copy_dir(source, &unpack_dir)
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+        }
+
+        let root = find_toolchain_root(&unpack_dir)?;
+        fs::rename(&root, staging_dir)?;
+        if unpack_dir.exists() {
+            fs::remove_dir_all(&unpack_dir)?;
+        }
+        Ok(())
+    })?;
+
+    Ok(name)
+}
+
+const TOOLCHAIN_USAGE: &str = "\
+Usage: vargo toolchain <command>
+
+Commands:
+    install <dir|archive> [--name <name>] [--force]   Install a Verus toolchain (.tar, .tar.gz, .tgz or .zip)
+    uninstall <name>                                  Remove an installed toolchain
+    list                                              List available toolchains
+    default [<name>]                                  Show or set the default toolchain
+
+Use `vargo +<name> ...` to run a single command with a specific toolchain.";
+
+/// Entry point of `vargo toolchain ...`
+pub fn toolchain_command(args: &[String]) -> anyhow::Result<i32> {
+    let Some((cmd, args)) = args.split_first() else {
+        println!("{}", TOOLCHAIN_USAGE);
+        return Ok(0);
+    };
+
+    match cmd.as_str() {
+        "install" => {
+            let mut source = None;
+            let mut name = None;
+            let mut force = false;
+
+            let mut iter = args.iter();
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--name" => name = Some(iter.next().context("--name expects a value")?.as_str()),
+                    "--force" => force = true,
+                    _ if source.is_none() => source = Some(arg),
+                    _ => bail!("Unexpected argument `{}`\n\n{}", arg, TOOLCHAIN_USAGE),
+                }
+            }
+
+            let source = source.ok_or_else(|| anyhow!("Missing toolchain path\n\n{}", TOOLCHAIN_USAGE))?;
+            let name = install_toolchain(Path::new(source), name, force)?;
+            println!("Installed toolchain `{}`", name);
+        }
+
+        "uninstall" => {
+            let [name] = args else {
+                bail!("Expected exactly one toolchain name\n\n{}", TOOLCHAIN_USAGE);
+            };
+            check_toolchain_name(name)?;
+
+            fs::remove_dir_all(toolchain_dir(name)?)
+                .with_context(|| format!("Failed to remove toolchain `{}`", name))?;
+            println!("Uninstalled toolchain `{}`", name);
+        }
+
+        "list" => {
+            let default = default_toolchain()?;
+
+            for name in available_toolchains()? {
+                let mut notes = Vec::new();
+                if name == EMBEDDED_TOOLCHAIN {
+                    notes.push("built-in");
+                }
+                if default.as_deref() == Some(name.as_str()) {
+                    notes.push("default");
+                }
+
+                if notes.is_empty() {
+                    println!("{}", name);
+                } else {
+                    println!("{} ({})", name, notes.join(", "));
+                }
+            }
+        }
+
+        "default" => match args {
+            [] => match default_toolchain()? {
+                Some(name) => println!("{}", name),
+                None => println!("No default toolchain (set one with `vargo toolchain default <name>`)"),
+            },
+            [name] => {
+                // Make sure the toolchain exists before making it the default
+                toolchain_dir(name)?;
+
+                let mut settings = read_settings()?;
+                settings.insert("default_toolchain".to_string(), toml::Value::String(name.clone()));
+
+                let path = settings_path()?;
+                fs::create_dir_all(vargo_home()?)?;
+                fs::write(&path, toml::to_string(&settings)?)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("Default toolchain set to `{}`", name);
+            }
+            _ => bail!("Expected at most one toolchain name\n\n{}", TOOLCHAIN_USAGE),
+        },
+
+        _ => bail!("Unknown toolchain command `{}`\n\n{}", cmd, TOOLCHAIN_USAGE),
+    }
+
+    Ok(0)
+}
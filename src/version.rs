@@ -0,0 +1,152 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context};
+
+use crate::toolchain::{self, HAS_EMBEDDED_TOOLCHAIN};
+
+/// Provenance of the embedded Verus toolchain (recorded in build.rs)
+pub struct Provenance {
+    /// Git URL (or local path) of the Verus repo
+    pub repo: &'static str,
+    /// Checked-out branch (`HEAD` if detached)
+    pub branch: &'static str,
+    /// Full commit hash
+    pub commit: &'static str,
+    /// Whether the checkout had uncommitted changes (`None` if unknown)
+    pub dirty: Option<bool>,
+    /// Where Verus came from: `remote` (`VERUS_REPO` or the default repo), `local` (`VERUS_LOCAL`),
+    /// `tarball` (`VERUS_SOURCE_TARBALL`) or `prebuilt` (`VERUS_PREBUILT`)
+    pub source: &'static str,
+    pub z3_version: &'static str,
+    /// Cargo features Verus was built with (comma-separated)
+    pub build_features: &'static str,
+    /// Extra arguments passed to Verus's build (`VERUS_BUILD_ARGS`)
+    pub build_args: &'static str,
+    pub build_date: &'static str,
+}
+
+#[cfg(feature = "embedded-verus")]
+pub const EMBEDDED_PROVENANCE: Provenance = Provenance {
+    repo: env!("VARGO_VERUS_REPO"),
+    branch: env!("VARGO_VERUS_BRANCH"),
+    commit: env!("VARGO_VERUS_COMMIT"),
+    dirty: match env!("VARGO_VERUS_DIRTY").as_bytes() {
+        b"true" => Some(true),
+        b"false" => Some(false),
+        _ => None,
+    },
+    source: env!("VARGO_VERUS_SOURCE"),
+    z3_version: env!("VARGO_Z3_VERSION"),
+    build_features: env!("VARGO_VERUS_BUILD_FEATURES"),
+    build_args: env!("VARGO_VERUS_BUILD_ARGS"),
+    build_date: env!("VARGO_BUILD_DATE"),
+};
+
+/// Without the `embedded-verus` feature there is no embedded Verus, so nothing is known about it
+#[cfg(not(feature = "embedded-verus"))]
+pub const EMBEDDED_PROVENANCE: Provenance = Provenance {
+    repo: "",
+    branch: "",
+    commit: "",
+    dirty: None,
+    source: "",
+    z3_version: "",
+    build_features: "",
+    build_args: "",
+    build_date: "",
+};
+
+/// Replace empty strings with "unknown" for printing
+fn or_unknown(s: &str) -> &str {
+    if s.is_empty() { "unknown" } else { s }
+}
+
+/// Get the (trimmed) output of `<program> <args> --version`, failing if it does not succeed
+fn command_version(program: &str, args: &[String]) -> anyhow::Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!("{} --version failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The version line to print for a tool, or why it is unavailable
+fn version_line(name: &str, version: &anyhow::Result<String>) -> String {
+    match version {
+        Ok(version) => version.clone(),
+        Err(err) => format!("{}: unavailable ({:#})", name, err),
+    }
+}
+
+/// Entry point of `vargo version [--json]`
+pub fn version_command(args: &[String], verus_path: &str) -> anyhow::Result<i32> {
+    let json = match args {
+        [] => false,
+        [flag] if flag == "--json" => true,
+        _ => bail!("Usage: vargo version [--json]"),
+    };
+
+    let verus_version = command_version(verus_path, &[]);
+    // The cargo that vargo runs: the one of the Rust toolchain required by Verus (if any)
+    let cargo_version = Path::new(verus_path).parent()
+        .context("Failed to get the toolchain directory")
+        .and_then(toolchain::rust_toolchain_arg)
+        .and_then(|rust_toolchain_arg| command_version("cargo", rust_toolchain_arg.as_slice()));
+    let provenance = &EMBEDDED_PROVENANCE;
+
+    if json {
+        let value = serde_json::json!({
+            "vargo": env!("CARGO_PKG_VERSION"),
+            "verus": {
+                "path": verus_path,
+                "version": verus_version.as_ref().ok(),
+            },
+            "cargo": cargo_version.as_ref().ok(),
+            "embedded": HAS_EMBEDDED_TOOLCHAIN.then(|| serde_json::json!({
+                "repo": provenance.repo,
+                "branch": provenance.branch,
+                "commit": provenance.commit,
+                "dirty": provenance.dirty,
+                "source": provenance.source,
+                "z3_version": provenance.z3_version,
+                "build_features": provenance.build_features,
+                "build_args": provenance.build_args,
+                "build_date": provenance.build_date,
+            })),
+        });
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("vargo {}", env!("CARGO_PKG_VERSION"));
+        println!("{}", version_line("verus", &verus_version));
+        println!("{}", version_line("cargo", &cargo_version));
+
+        println!();
+        if !HAS_EMBEDDED_TOOLCHAIN {
+            println!("Embedded Verus: none (built without the `embedded-verus` feature)");
+            return Ok(0);
+        }
+        println!("Embedded Verus:");
+        println!("  source:     {}", or_unknown(provenance.source));
+        println!("  repo:       {}", or_unknown(provenance.repo));
+        println!("  branch:     {}", or_unknown(provenance.branch));
+        println!("  commit:     {}{}", or_unknown(provenance.commit), match provenance.dirty {
+            Some(true) => " (dirty)",
+            _ => "",
+        });
+        println!("  z3:         {}", or_unknown(provenance.z3_version));
+        if !provenance.build_features.is_empty() {
+            println!("  features:   {}", provenance.build_features);
+        }
+        if !provenance.build_args.is_empty() {
+            println!("  build args: {}", provenance.build_args);
+        }
+        println!("  build date: {}", or_unknown(provenance.build_date));
+    }
+
+    Ok(0)
+}
@@ -0,0 +1,109 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Passes a `RUSTC_WRAPPER` configured before vargo (e.g. sccache) to vargo running as `RUSTC_WRAPPER`
+pub const INNER_WRAPPER_VAR: &str = "VARGO_INNER_RUSTC_WRAPPER";
+
+/// Passes a `RUSTC_WORKSPACE_WRAPPER` configured before vargo to vargo running as `RUSTC_WRAPPER`
+/// (vargo itself is the `RUSTC_WORKSPACE_WRAPPER` to tell workspace members apart)
+pub const INNER_WORKSPACE_WRAPPER_VAR: &str = "VARGO_INNER_RUSTC_WORKSPACE_WRAPPER";
+
+/// Cargo config files that apply in the current directory, from the highest precedence
+/// (the current directory) to the lowest (`$CARGO_HOME`)
+pub fn cargo_config_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(cwd) = env::current_dir() {
+        for dir in cwd.ancestors() {
+            files.push(dir.join(".cargo").join("config.toml"));
+            files.push(dir.join(".cargo").join("config"));
+        }
+    }
+
+    let cargo_home = env::var("CARGO_HOME").map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".cargo")));
+    if let Ok(cargo_home) = cargo_home {
+        files.push(cargo_home.join("config.toml"));
+        files.push(cargo_home.join("config"));
+    }
+
+    let mut existing: Vec<PathBuf> = Vec::new();
+    for file in files {
+        if file.is_file() && !existing.contains(&file) {
+            existing.push(file);
+        }
+    }
+    existing
+}
+
+/// Reads `build.<key>` from the cargo config files, returning the value and the file it is set in
+///
+/// Like cargo, paths with a `/` are relative to the directory containing `.cargo`,
+/// while plain program names are looked up in `PATH`.
+fn cargo_config_program(key: &str) -> Option<(String, PathBuf)> {
+    for file in cargo_config_files() {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let Ok(parsed) = toml::from_str::<toml::Table>(&content) else {
+            continue;
+        };
+
+        if let Some(program) = parsed.get("build").and_then(|build| build.get(key)).and_then(|value| value.as_str()) {
+            let base = file.parent().and_then(Path::parent).unwrap_or(Path::new("."));
+            let program = if program.contains('/') && Path::new(program).is_relative() {
+                base.join(program).to_string_lossy().to_string()
+            } else {
+                program.to_string()
+            };
+            return Some((program, file));
+        }
+    }
+    None
+}
+
+/// A rustc wrapper configured outside vargo
+pub struct ExistingWrapper {
+    pub program: String,
+    /// Where it is configured (an environment variable or a cargo config file)
+    pub source: String,
+}
+
+/// Finds the wrapper that cargo would use for `RUSTC_WRAPPER` (or `RUSTC_WORKSPACE_WRAPPER`
+/// if `workspace` is set) without vargo, from the environment or the cargo config
+///
+/// An empty value disables the wrapper, as in cargo.
+pub fn existing_wrapper(workspace: bool) -> Option<ExistingWrapper> {
+    let (var, config_var, key) = if workspace {
+        ("RUSTC_WORKSPACE_WRAPPER", "CARGO_BUILD_RUSTC_WORKSPACE_WRAPPER", "rustc-workspace-wrapper")
+    } else {
+        ("RUSTC_WRAPPER", "CARGO_BUILD_RUSTC_WRAPPER", "rustc-wrapper")
+    };
+
+    let (program, source) = if let Ok(program) = env::var(var) {
+        (program, var.to_string())
+    } else if let Ok(program) = env::var(config_var) {
+        (program, config_var.to_string())
+    } else {
+        let (program, file) = cargo_config_program(key)?;
+        (program, format!("build.{} in {}", key, file.display()))
+    };
+
+    if program.is_empty() {
+        return None;
+    }
+
+    // Do not wrap vargo in itself (e.g. if a previous vargo's environment leaked)
+    if is_vargo(&program) {
+        return None;
+    }
+
+    Some(ExistingWrapper { program, source })
+}
+
+/// Whether `program` is the running vargo executable
+pub fn is_vargo(program: &str) -> bool {
+    env::current_exe().ok()
+        .zip(Path::new(program).canonicalize().ok())
+        .is_some_and(|(exe, program)| exe == program)
+}